@@ -1,22 +1,31 @@
-use std::collections::HashMap;
-use std::fs::File;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
 use std::path::Path;
-use std::io::{BufRead, BufReader, Error};
+use std::io::{Error, ErrorKind};
 
-struct Engine {
-    grid: Vec<Vec<Cell>>,
+use crate::random::Random;
+
+pub mod render;
+pub mod schematic;
+pub mod stats;
+
+// one byte per character, row after row
+pub struct Engine {
+    grid: Vec<u8>,
     rows: usize,
     cols: usize,
 }
 
+// handle on a grid cell, its coordinates are computed from the index
 #[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
 #[derive(Eq)]
 #[derive(PartialEq)]
 #[derive(Hash)]
-struct Cell {
-    value: char,
-    row: usize,
-    col: usize,
+pub struct Cell {
+    index: usize,
 }
 
 #[derive(Debug)]
@@ -30,74 +39,520 @@ struct Number {
     end_col: usize,
 }
 
-pub fn number_sum(filename: String) -> Result<u32, Error>
+// what lies beyond the edges of the grid
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum Edges {
+    // nothing, neighbors are clipped
+    Clip,
+    // the opposite edge, numbers may continue from the last column to the first one
+    Wrap,
+}
+
+// what to do with rows shorter or longer than the first one
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum RaggedRows {
+    Reject,
+    // pad every row with '.' up to the longest one
+    Pad,
+}
+
+// rows and columns are numbered from 1, as in the file
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum GridError {
+    Empty,
+    RaggedRow { row: usize, expected: usize, found: usize },
+    NonAscii { row: usize, col: usize },
+    OutOfGrid { row: usize, col: usize },
+}
+
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GridError::Empty => write!(f, "empty schematic"),
+            GridError::RaggedRow { row, expected, found } =>
+                write!(f, "row {} has {} columns, expected {}", row, found, expected),
+            GridError::NonAscii { row, col } =>
+                write!(f, "non ASCII character at row {}, column {}", row, col),
+            GridError::OutOfGrid { row, col } =>
+                write!(f, "row {}, column {} is out of the grid", row, col),
+        }
+    }
+}
+
+impl std::error::Error for GridError {}
+
+impl From<GridError> for Error {
+    fn from(error: GridError) -> Error {
+        Error::new(ErrorKind::InvalidData, error)
+    }
+}
+
+// digit runs labeled by number id
+struct Labeling {
+    labels: Vec<u32>,
+    values: Vec<u32>,
+    // runs too long for a u32, never parts
+    oversized: Vec<bool>,
+}
+
+const NO_NUMBER: u32 = u32::MAX;
+
+const SYMBOLS: &[u8] = b"*#+$/@%=&-";
+
+impl Engine {
+    fn cell(&self, row: usize, col: usize) -> Cell {
+        Cell { index: row * self.cols + col }
+    }
+
+    fn cells(&self) -> impl Iterator<Item=Cell> {
+        (0..self.grid.len()).map(|index| Cell { index })
+    }
+
+    fn value(&self, cell: Cell) -> char {
+        self.grid[cell.index] as char
+    }
+
+    fn line(&self, row: usize) -> &[u8] {
+        &self.grid[row * self.cols..(row + 1) * self.cols]
+    }
+
+    // cell at an offset from (row, col), None when it falls off the grid
+    fn shift(&self, row: usize, col: usize, row_offset: isize, col_offset: isize, edges: Edges) -> Option<Cell> {
+        let row = row as isize + row_offset;
+        let col = col as isize + col_offset;
+        match edges {
+            Edges::Clip => {
+                if row < 0 || row >= self.rows as isize || col < 0 || col >= self.cols as isize {
+                    return None;
+                }
+                Some(self.cell(row as usize, col as usize))
+            },
+            Edges::Wrap => Some(self.cell(row.rem_euclid(self.rows as isize) as usize, col.rem_euclid(self.cols as isize) as usize)),
+        }
+    }
+}
+
+impl Number {
+    // columns of the digits, a wrapped number ends before it starts
+    fn cols(&self, engine_cols: usize) -> impl Iterator<Item=usize> {
+        let start_col = self.start_col;
+        let length = if self.start_col <= self.end_col {
+            self.end_col - self.start_col + 1
+        } else {
+            engine_cols - self.start_col + self.end_col + 1
+        };
+        (0..length).map(move |i| (start_col + i) % engine_cols)
+    }
+
+    fn contains(&self, row: usize, col: usize) -> bool {
+        if row != self.row {
+            return false;
+        }
+        if self.start_col <= self.end_col {
+            self.start_col <= col && col <= self.end_col
+        } else {
+            col >= self.start_col || col <= self.end_col
+        }
+    }
+}
+
+// cells considered adjacent to a digit, as (row, col) offsets
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum Neighborhood {
+    // 4 orthogonal cells
+    VonNeumann,
+    // 8 cells, including diagonals
+    Moore,
+    // (2k+1)^2 - 1 cells within Chebyshev distance k
+    MooreRadius(usize),
+    Custom(Vec<(isize, isize)>),
+}
+
+impl Neighborhood {
+    fn offsets(&self) -> Vec<(isize, isize)> {
+        match self {
+            Neighborhood::VonNeumann => vec![(-1, 0), (0, -1), (0, 1), (1, 0)],
+            Neighborhood::Moore => Neighborhood::MooreRadius(1).offsets(),
+            Neighborhood::MooreRadius(radius) => {
+                let k = *radius as isize;
+                (-k..=k)
+                    .flat_map(|row| (-k..=k).map(move |col| (row, col)))
+                    .filter(|offset| *offset != (0, 0))
+                    .collect()
+            },
+            Neighborhood::Custom(offsets) => offsets.clone(),
+        }
+    }
+}
+
+// which cells are gears and how the numbers around them combine
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct GearRule {
+    symbols: Vec<char>,
+    count: Count,
+    combine: Combine,
+}
+
+// number of adjacent numbers a gear requires
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum Count {
+    Exact(usize),
+    AtLeast(usize),
+    AtMost(usize),
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum Combine {
+    Product,
+    Sum,
+    Max,
+}
+
+impl GearRule {
+    pub fn new(symbols: &str, count: Count, combine: Combine) -> GearRule {
+        GearRule {
+            symbols: symbols.chars().collect(),
+            count,
+            combine,
+        }
+    }
+
+    // the puzzle rule: a '*' touching exactly 2 numbers, multiplied together
+    pub fn ratio() -> GearRule {
+        GearRule::new("*", Count::Exact(2), Combine::Product)
+    }
+
+    // dots and digits are never gears, even when listed in the rule
+    fn gear(&self, c: char) -> bool {
+        symbol(c) && self.symbols.contains(&c)
+    }
+}
+
+impl Count {
+    fn matches(&self, count: usize) -> bool {
+        match self {
+            Count::Exact(n) => count == *n,
+            Count::AtLeast(n) => count >= *n,
+            Count::AtMost(n) => count <= *n,
+        }
+    }
+}
+
+impl Combine {
+    // a gear without any adjacent number is worth 0,
+    // a product too large for u64 saturates at u64::MAX
+    fn apply(&self, values: &[u32]) -> u64 {
+        if values.is_empty() {
+            return 0;
+        }
+
+        let values = values.iter()
+            .map(|value| *value as u64);
+
+        match self {
+            Combine::Product => values.fold(1, u64::saturating_mul),
+            Combine::Sum => values.sum(),
+            Combine::Max => values.max().unwrap(),
+        }
+    }
+}
+
+pub fn number_sum(filename: String, neighborhood: &Neighborhood) -> Result<u64, Error>
 {
     let engine = engine(filename)?;
 
-    let numbers = numbers(&engine);
+    let sum = part_number_sum(&engine, neighborhood);
 
-    let adjacent_cells = adjacent_cells_map(&numbers, &engine);
+    Ok(sum)
+}
 
-    let sum = numbers
-        .iter()
-        .filter(|number| !adjacent_cells.get(*number)
-                                    .unwrap()
-                                    .iter()
-                                    .all(|cell| cell.value == '.'))
-        .map(|number| number.value)
+pub fn gear_ratio_sum(filename: String, neighborhood: &Neighborhood) -> Result<u64, Error>
+{
+    let sums = gear_sums(filename, neighborhood, &GearRule::ratio())?;
+
+    let sum = sums.values()
         .sum();
 
     Ok(sum)
 }
 
-pub fn gear_ratio_sum(filename: String) -> Result<u32, Error>
+// sum of the gear values, broken down per gear symbol
+pub fn gear_sums(filename: String, neighborhood: &Neighborhood, rule: &GearRule) -> Result<BTreeMap<char, u64>, Error>
 {
     let engine = engine(filename)?;
 
-    let numbers = numbers(&engine);
+    let sums = gear_rule_sums(&engine, neighborhood, rule);
+
+    Ok(sums)
+}
+
+pub fn part_number_sum(engine: &Engine, neighborhood: &Neighborhood) -> u64 {
+    part_number_sum_with(engine, neighborhood, Edges::Clip)
+}
+
+pub fn part_number_sum_with(engine: &Engine, neighborhood: &Neighborhood, edges: Edges) -> u64 {
+    let numbers = numbers_with(engine, edges);
+
+    let adjacent_cells = adjacent_cells_map(&numbers, engine, neighborhood, edges);
+
+    numbers
+        .iter()
+        .zip(adjacent_cells.iter())
+        .filter(|(_, cells)| !cells.is_empty())
+        .map(|(number, _)| number.value as u64)
+        .sum()
+}
+
+pub fn gear_rule_sums(engine: &Engine, neighborhood: &Neighborhood, rule: &GearRule) -> BTreeMap<char, u64> {
+    gear_rule_sums_with(engine, neighborhood, rule, Edges::Clip)
+}
+
+pub fn gear_rule_sums_with(engine: &Engine, neighborhood: &Neighborhood, rule: &GearRule, edges: Edges) -> BTreeMap<char, u64> {
+    let numbers = numbers_with(engine, edges);
 
-    let adjacent_cells = adjacent_cells_map(&numbers, &engine);
+    let adjacent_cells = adjacent_cells_map(&numbers, engine, neighborhood, edges);
 
     let adjacent_numbers = adjacent_numbers_map(&adjacent_cells);
 
-    let sum = engine.grid
+    let mut sums: BTreeMap<char, u64> = rule.symbols.iter()
+        .map(|symbol| (*symbol, 0))
+        .collect();
+
+    engine.cells()
+        .filter(|cell| rule.gear(engine.value(*cell)))
+        .for_each(|cell| {
+            let values: Vec<u32> = adjacent_numbers.get(&cell)
+                .map(|ids| ids.iter().map(|id| numbers[*id].value).collect())
+                .unwrap_or_default();
+            if rule.count.matches(values.len()) {
+                let sum = sums.get_mut(&engine.value(cell)).unwrap();
+                *sum = sum.saturating_add(rule.combine.apply(&values));
+            }
+        });
+
+    sums
+}
+
+// part number sum of the schematic repeated tiles_down x tiles_across times
+pub fn tiled_part_number_sum(engine: &Engine, neighborhood: &Neighborhood, tiles_down: usize, tiles_across: usize) -> u64 {
+    let tiling = tiling(engine, neighborhood, tiles_down, tiles_across);
+    let tiled = &tiling.engine;
+
+    let numbers = numbers(tiled);
+
+    let adjacent_cells = adjacent_cells_map(&numbers, tiled, neighborhood, Edges::Clip);
+
+    numbers
         .iter()
-        .flatten()
-        .filter(|cell| cell.value == '*')
-        .map(|cell| adjacent_numbers.get(cell)
-                            .unwrap())
-        .filter(|numbers| numbers.len() == 2)
-        .map(gear_ratio)
-        .sum();
+        .zip(adjacent_cells.iter())
+        .filter(|(_, cells)| !cells.is_empty())
+        .map(|(number, _)| (number.value as u64).saturating_mul(tiling.weight(number.row, number.start_col)))
+        .fold(0, u64::saturating_add)
+}
 
-    Ok(sum)
+// gear sums of the schematic repeated tiles_down x tiles_across times
+pub fn tiled_gear_rule_sums(engine: &Engine, neighborhood: &Neighborhood, rule: &GearRule, tiles_down: usize, tiles_across: usize) -> BTreeMap<char, u64> {
+    let tiling = tiling(engine, neighborhood, tiles_down, tiles_across);
+    let tiled = &tiling.engine;
+
+    let numbers = numbers(tiled);
+
+    let adjacent_cells = adjacent_cells_map(&numbers, tiled, neighborhood, Edges::Clip);
+
+    let adjacent_numbers = adjacent_numbers_map(&adjacent_cells);
+
+    let mut sums: BTreeMap<char, u64> = rule.symbols.iter()
+        .map(|symbol| (*symbol, 0))
+        .collect();
+
+    tiled.cells()
+        .filter(|cell| rule.gear(tiled.value(*cell)))
+        .for_each(|cell| {
+            let values: Vec<u32> = adjacent_numbers.get(&cell)
+                .map(|ids| ids.iter().map(|id| numbers[*id].value).collect())
+                .unwrap_or_default();
+            if rule.count.matches(values.len()) {
+                let weight = tiling.weight(cell.index / tiled.cols, cell.index % tiled.cols);
+                let sum = sums.get_mut(&tiled.value(cell)).unwrap();
+                *sum = sum.saturating_add(rule.combine.apply(&values).saturating_mul(weight));
+            }
+        });
+
+    sums
+}
+
+// the schematic repeated tiles_down x tiles_across times
+pub fn tile(engine: &Engine, tiles_down: usize, tiles_across: usize) -> Engine {
+    assert!(tiles_down > 0 && tiles_across > 0, "at least one tile is needed");
+
+    let rows = engine.rows * tiles_down;
+    let cols = engine.cols * tiles_across;
+
+    let mut grid = Vec::with_capacity(rows * cols);
+
+    for _ in 0..tiles_down {
+        for row in 0..engine.rows {
+            for _ in 0..tiles_across {
+                grid.extend_from_slice(engine.line(row));
+            }
+        }
+    }
+
+    Engine { grid, rows, cols }
+}
+
+// same as part_number_sum, in a single pass over the labeled grid
+pub fn part_number_sum_labeled(engine: &Engine, neighborhood: &Neighborhood) -> u64 {
+    let labeling = labeling(engine);
+
+    let offsets = neighborhood.offsets();
+
+    let mut part = vec![false; labeling.values.len()];
+
+    engine.cells()
+        .filter(|cell| symbol(engine.value(*cell)))
+        .for_each(|cell| {
+            for id in adjacent_ids(cell, &labeling, engine, &offsets) {
+                part[id as usize] = true;
+            }
+        });
+
+    labeling.values
+        .iter()
+        .zip(part.iter())
+        .filter(|(_, part)| **part)
+        .map(|(value, _)| *value as u64)
+        .sum()
+}
+
+// same as gear_rule_sums, in a single pass over the labeled grid
+pub fn gear_rule_sums_labeled(engine: &Engine, neighborhood: &Neighborhood, rule: &GearRule) -> BTreeMap<char, u64> {
+    let labeling = labeling(engine);
+
+    let offsets = neighborhood.offsets();
+
+    let mut sums: BTreeMap<char, u64> = rule.symbols.iter()
+        .map(|symbol| (*symbol, 0))
+        .collect();
+
+    engine.cells()
+        .filter(|cell| rule.gear(engine.value(*cell)))
+        .for_each(|cell| {
+            let values: Vec<u32> = adjacent_ids(cell, &labeling, engine, &offsets)
+                .iter()
+                .map(|id| labeling.values[*id as usize])
+                .collect();
+            if rule.count.matches(values.len()) {
+                let sum = sums.get_mut(&engine.value(cell)).unwrap();
+                *sum = sum.saturating_add(rule.combine.apply(&values));
+            }
+        });
+
+    sums
+}
+
+// schematic-like grid: mostly dots, with numbers up to 3 digits and a few symbols
+pub fn random_engine(rows: usize, cols: usize, seed: u64) -> Engine {
+    let mut random = Random::new(seed);
+
+    let mut grid = Vec::with_capacity(rows * cols);
+
+    for _ in 0..rows {
+        let mut col = 0;
+        while col < cols {
+            let draw = random.below(100);
+            if draw < 8 {
+                let length = (1 + random.below(3) as usize).min(cols - col);
+                grid.push(b'1' + random.below(9) as u8);
+                for _ in 1..length {
+                    grid.push(b'0' + random.below(10) as u8);
+                }
+                col += length;
+                if col < cols {
+                    grid.push(b'.');
+                    col += 1;
+                }
+                continue;
+            }
+
+            if draw < 12 {
+                grid.push(SYMBOLS[random.below(SYMBOLS.len() as u64) as usize]);
+            } else {
+                grid.push(b'.');
+            }
+            col += 1;
+        }
+    }
+
+    Engine { grid, rows, cols }
 }
 
-fn engine(filename: String) -> Result<Engine, Error> {
+pub fn engine(filename: String) -> Result<Engine, Error> {
+    engine_with(filename, RaggedRows::Reject)
+}
+
+pub fn engine_with(filename: String, ragged_rows: RaggedRows) -> Result<Engine, Error> {
     let path = Path::new(&filename);
-    let file = File::open(path)?;
-    let buf_reader = BufReader::new(file);
+    let text = fs::read_to_string(path)?;
 
-    let mut rows = Vec::new();
+    let engine = parse_engine(&text, ragged_rows)?;
 
-    let lines: Vec<String> = buf_reader.lines()
-        .flatten()
+    Ok(engine)
+}
+
+pub fn parse_engine(text: &str, ragged_rows: RaggedRows) -> Result<Engine, GridError> {
+    // accept \n, \r\n and \r line endings
+    let text = text.replace("\r\n", "\n")
+        .replace('\r', "\n");
+
+    let mut lines: Vec<&str> = text.split('\n')
         .collect();
 
-    for (row_index, line) in lines.iter().enumerate() {
-        let mut row = Vec::new();
-        for (col_index, c) in line.chars().enumerate() {
-           let cell = Cell { value: c, row: row_index, col: col_index };
-           row.push(cell);
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+
+    if lines.is_empty() {
+        return Err(GridError::Empty);
+    }
+
+    for (row, line) in lines.iter().enumerate() {
+        if let Some(col) = line.chars().position(|c| !c.is_ascii()) {
+            return Err(GridError::NonAscii { row: row + 1, col: col + 1 });
         }
-        rows.push(row);
     }
 
-    let row_size = rows.len();
+    let rows = lines.len();
+
+    let cols = match ragged_rows {
+        RaggedRows::Reject => lines[0].len(),
+        RaggedRows::Pad => lines.iter().map(|line| line.len()).max().unwrap(),
+    };
 
-    let col_size = rows[0].len();
+    let mut grid = Vec::with_capacity(rows * cols);
+
+    for (row, line) in lines.iter().enumerate() {
+        if line.len() != cols && ragged_rows == RaggedRows::Reject {
+            return Err(GridError::RaggedRow { row: row + 1, expected: cols, found: line.len() });
+        }
+        grid.extend_from_slice(line.as_bytes());
+        grid.resize((row + 1) * cols, b'.');
+    }
 
-    let engine = Engine { grid: rows, rows: row_size, cols: col_size };
+    let engine = Engine { grid, rows, cols };
 
     Ok(engine)
 }
@@ -105,32 +560,31 @@ fn engine(filename: String) -> Result<Engine, Error> {
 fn numbers(engine: &Engine) -> Vec<Number>
 {
     let mut numbers = Vec::new();
-    for (row_index, row) in engine.grid.iter().enumerate() {
+    for row_index in 0..engine.rows {
+        let row = engine.line(row_index);
         let mut col_index = 0;
-        
+
         loop {
-            while col_index < row.len() && !row[col_index].value.is_ascii_digit() {
+            while col_index < row.len() && !row[col_index].is_ascii_digit() {
                 col_index += 1;
             }
             if col_index == row.len() {
                 break;
             }
             let start_col = col_index;
-            while col_index < row.len() && row[col_index].value.is_ascii_digit() {
+            while col_index < row.len() && row[col_index].is_ascii_digit() {
                 col_index += 1;
             }
             let end_col = col_index - 1;
 
-            // parse the chars from start_col to end_col as an int
-            // storing the value in value variable
-            let value = row[start_col..=end_col].iter()
-                .map(|cell| cell.value)
-                .collect::<String>()
-                .parse::<u32>()
-                .expect("invalid number format");
+            // parse the digits from start_col to end_col as an int, runs too long for a u32 are never parts
+            let value = match std::str::from_utf8(&row[start_col..=end_col]).unwrap().parse::<u32>() {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
 
             let number = Number {
-                value: value,
+                value,
                 row: row_index,
                 start_col,
                 end_col,
@@ -142,66 +596,222 @@ fn numbers(engine: &Engine) -> Vec<Number>
     numbers
 }
 
-fn adjacent_cells_map<'a>(numbers: &'a Vec<Number>, engine: &'a Engine) -> HashMap<&'a Number, Vec<&'a Cell>>
-{
-    let mut adjacent_cells = HashMap::new();
+fn numbers_with(engine: &Engine, edges: Edges) -> Vec<Number> {
+    match edges {
+        Edges::Clip => numbers(engine),
+        Edges::Wrap => numbers_wrapped(engine),
+    }
+}
 
-    for number in numbers {
-        adjacent_cells.insert(number, adjacent_cells_vec(number, engine));
+// numbers of each row read as a circle
+fn numbers_wrapped(engine: &Engine) -> Vec<Number> {
+    let mut numbers = Vec::new();
+    for row_index in 0..engine.rows {
+        let row = engine.line(row_index);
+
+        // start right after a non digit, so that no number is cut in two
+        let first_col = match row.iter().position(|c| !c.is_ascii_digit()) {
+            Some(col) => col + 1,
+            None => {
+                numbers.extend(wrapped_number(row_index, row, &(0..row.len()).collect::<Vec<usize>>()));
+                continue;
+            },
+        };
+
+        let mut digit_cols = Vec::new();
+        for step in 0..row.len() {
+            let col = (first_col + step) % row.len();
+            if row[col].is_ascii_digit() {
+                digit_cols.push(col);
+                continue;
+            }
+            if !digit_cols.is_empty() {
+                numbers.extend(wrapped_number(row_index, row, &digit_cols));
+                digit_cols.clear();
+            }
+        }
     }
+    numbers
+}
 
-    adjacent_cells
+// None for a run too long for a u32, which is never a part
+fn wrapped_number(row_index: usize, row: &[u8], digit_cols: &[usize]) -> Option<Number> {
+    let value = digit_cols.iter()
+        .map(|col| (row[*col] - b'0') as u32)
+        .try_fold(0u32, |value, digit| value.checked_mul(10)?.checked_add(digit))?;
+
+    Some(Number {
+        value,
+        row: row_index,
+        start_col: digit_cols[0],
+        end_col: digit_cols[digit_cols.len() - 1],
+    })
+}
+
+// middle tiles are all alike, so only a few of them are materialized
+struct Tiling {
+    engine: Engine,
+    tile_rows: usize,
+    tile_cols: usize,
+    // how many tiles of the full grid each materialized tile stands for
+    row_weights: Vec<u64>,
+    col_weights: Vec<u64>,
 }
 
-fn adjacent_cells_vec<'a>(number: &'a Number, engine: &'a Engine) -> Vec<&'a Cell> {
-    let mut adjacent_cells = Vec::new();
+impl Tiling {
+    fn weight(&self, row: usize, col: usize) -> u64 {
+        self.row_weights[row / self.tile_rows].saturating_mul(self.col_weights[col / self.tile_cols])
+    }
+}
+
+fn tiling(engine: &Engine, neighborhood: &Neighborhood, tiles_down: usize, tiles_across: usize) -> Tiling {
+    let offsets = neighborhood.offsets();
+    let row_reach = offsets.iter()
+        .map(|(row_offset, _)| row_offset.unsigned_abs())
+        .max()
+        .unwrap_or(0);
+    let col_reach = offsets.iter()
+        .map(|(_, col_offset)| col_offset.unsigned_abs())
+        .max()
+        .unwrap_or(0);
+
+    // number of tiles the neighborhood of a tile spans on each side,
+    // a number starting in a tile may also end in the next one
+    let mut tile_reach = 1 + col_reach.div_ceil(engine.cols);
+
+    // a row of digits only joins across every tile: enough tiles are kept for the run
+    // to be either whole or too long for a u32 in the materialized grid as in the full one
+    if (0..engine.rows).any(|row| engine.line(row).iter().all(|c| c.is_ascii_digit())) {
+        tile_reach = tile_reach.max(11usize.div_ceil(engine.cols));
+    }
+
+    let row_weights = tile_weights(tiles_down, row_reach.div_ceil(engine.rows));
+    let col_weights = tile_weights(tiles_across, tile_reach);
+
+    Tiling {
+        engine: tile(engine, row_weights.len(), col_weights.len()),
+        tile_rows: engine.rows,
+        tile_cols: engine.cols,
+        row_weights,
+        col_weights,
+    }
+}
+
+// 1 for the tiles close to the edges, the remaining count for the middle one
+fn tile_weights(tiles: usize, reach: usize) -> Vec<u64> {
+    if tiles <= 2 * reach + 1 {
+        return vec![1; tiles];
+    }
 
-    let row = number.row;
-    let start_col = number.start_col;
-    let end_col = number.end_col;
+    let mut weights = vec![1; 2 * reach + 1];
+    weights[reach] = (tiles - 2 * reach) as u64;
+    weights
+}
 
-    [row as isize - 1, row as isize + 1].iter()
-        .filter(|symbol_row| **symbol_row >= 0 && **symbol_row < engine.rows as isize)
-        .for_each(|symbol_row| {
-            (start_col as isize - 1 ..= end_col as isize + 1)
-                    .filter(|col| *col >= 0 && *col < engine.cols as isize)
-                    .map(|col| engine.grid[*symbol_row as usize].get(col as usize))
-                    .map(|cell| cell.unwrap())
-                    .filter(symbol)
-                    .for_each(|cell| adjacent_cells.push(cell));
+// symbol cells adjacent to each number, indexed by number id
+fn adjacent_cells_map(numbers: &[Number], engine: &Engine, neighborhood: &Neighborhood, edges: Edges) -> Vec<Vec<Cell>>
+{
+    let offsets = neighborhood.offsets();
+
+    numbers.iter()
+        .map(|number| adjacent_cells_vec(number, engine, &offsets, edges))
+        .collect()
+}
+
+fn adjacent_cells_vec(number: &Number, engine: &Engine, offsets: &[(isize, isize)], edges: Edges) -> Vec<Cell> {
+    let mut adjacent_cells: Vec<Cell> = Vec::new();
+
+    for col in number.cols(engine.cols) {
+        for (row_offset, col_offset) in offsets {
+            let cell = match engine.shift(number.row, col, *row_offset, *col_offset, edges) {
+                Some(cell) => cell,
+                None => continue,
+            };
+
+            // skip the digits of the number itself
+            if number.contains(cell.index / engine.cols, cell.index % engine.cols) {
+                continue;
             }
-        );
-    
-    [start_col as isize - 1, end_col as isize + 1].iter()
-        .filter(|symbol_col| **symbol_col >= 0 && **symbol_col < engine.cols as isize)
-        .map(|symbol_col| engine.grid[row].get(*symbol_col as usize))
-        .map(|cell| cell.unwrap())
-        .filter(symbol)
-        .for_each(|cell| adjacent_cells.push(cell));
+
+            if symbol(engine.value(cell)) && !adjacent_cells.contains(&cell) {
+                adjacent_cells.push(cell);
+            }
+        }
+    }
 
     adjacent_cells
 }
 
-fn adjacent_numbers_map<'a>(adjacent_cells_map: &'a HashMap<&Number, Vec<&Cell>>) -> HashMap<&'a Cell, Vec<&'a Number>>
+// ids of the numbers adjacent to each symbol cell
+fn adjacent_numbers_map(adjacent_cells_map: &[Vec<Cell>]) -> HashMap<Cell, Vec<usize>>
 {
     let mut adjacent_numbers = HashMap::new();
 
-    for (number, cells) in adjacent_cells_map {
-        for cell in cells { 
+    for (number_id, cells) in adjacent_cells_map.iter().enumerate() {
+        for cell in cells {
             adjacent_numbers.entry(*cell)
                 .or_insert(Vec::new())
-                .push(*number);
+                .push(number_id);
         }
     }
 
     adjacent_numbers
 }
 
-fn symbol(cell: &&Cell) -> bool {
-    let c = cell.value;
-    !(c == '.' || c.is_ascii_digit())
+// number id of every digit cell, NO_NUMBER elsewhere
+fn labeling(engine: &Engine) -> Labeling {
+    let mut labels = vec![NO_NUMBER; engine.grid.len()];
+    let mut values: Vec<u32> = Vec::new();
+    let mut oversized: Vec<bool> = Vec::new();
+
+    for (index, byte) in engine.grid.iter().enumerate() {
+        if !byte.is_ascii_digit() {
+            continue;
+        }
+
+        let digit = (byte - b'0') as u32;
+
+        // a digit continues the number on its left, unless it starts a row
+        if index % engine.cols > 0 && labels[index - 1] != NO_NUMBER {
+            let id = labels[index - 1] as usize;
+            match values[id].checked_mul(10).and_then(|value| value.checked_add(digit)) {
+                Some(value) => values[id] = value,
+                None => oversized[id] = true,
+            }
+            labels[index] = id as u32;
+        } else {
+            labels[index] = values.len() as u32;
+            values.push(digit);
+            oversized.push(false);
+        }
+    }
+
+    Labeling { labels, values, oversized }
 }
 
-fn gear_ratio(numbers: &Vec<&Number>) -> u32 {
-    numbers[0].value * numbers[1].value
-}
\ No newline at end of file
+// distinct ids of the numbers having a digit in the neighborhood of the cell
+fn adjacent_ids(cell: Cell, labeling: &Labeling, engine: &Engine, offsets: &[(isize, isize)]) -> Vec<u32> {
+    let mut ids = Vec::new();
+
+    let row = cell.index / engine.cols;
+    let col = cell.index % engine.cols;
+
+    // the digit d sees the cell when cell = d + offset
+    for (row_offset, col_offset) in offsets {
+        let digit = match engine.shift(row, col, -row_offset, -col_offset, Edges::Clip) {
+            Some(digit) => digit,
+            None => continue,
+        };
+
+        let id = labeling.labels[digit.index];
+        if id != NO_NUMBER && !labeling.oversized[id as usize] && !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    ids
+}
+
+fn symbol(c: char) -> bool {
+    !(c == '.' || c.is_ascii_digit())
+}
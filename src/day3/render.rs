@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Error;
+use std::path::Path;
+
+use super::{adjacent_ids, labeling, symbol, Cell, Engine, GearRule, Neighborhood, NO_NUMBER};
+
+const RESET: &str = "\x1b[0m";
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[1;33m";
+const CYAN: &str = "\x1b[36m";
+const GREY: &str = "\x1b[90m";
+
+const CELL_WIDTH: usize = 10;
+const CELL_HEIGHT: usize = 16;
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+enum Style {
+    PartNumber,
+    OtherNumber,
+    Gear,
+    Symbol,
+    Empty,
+}
+
+// style of every cell, along with the value of each gear
+struct Annotations {
+    styles: Vec<Style>,
+    gears: BTreeMap<usize, u64>,
+}
+
+// the schematic with ANSI colors: part numbers in green, other numbers in red,
+// gears in yellow followed by their value at the end of the row
+pub fn ansi(engine: &Engine, neighborhood: &Neighborhood, rule: &GearRule) -> String {
+    let annotations = annotations(engine, neighborhood, rule);
+
+    let mut output = String::new();
+
+    for row in 0..engine.rows {
+        let mut current = None;
+        for col in 0..engine.cols {
+            let cell = engine.cell(row, col);
+            let style = annotations.styles[cell.index];
+            if current != Some(style) {
+                output.push_str(color(style));
+                current = Some(style);
+            }
+            output.push(printable(engine.value(cell)));
+        }
+        output.push_str(RESET);
+
+        annotations.gears
+            .range(row * engine.cols..(row + 1) * engine.cols)
+            .for_each(|(index, value)| {
+                let cell = Cell { index: *index };
+                output.push_str(&format!("  {}{} col {} = {}{}", YELLOW, printable(engine.value(cell)), index % engine.cols + 1, value, RESET));
+            });
+        output.push('\n');
+    }
+
+    output
+}
+
+// the schematic as an SVG image, hovering a gear shows its value
+pub fn svg(engine: &Engine, neighborhood: &Neighborhood, rule: &GearRule) -> String {
+    let annotations = annotations(engine, neighborhood, rule);
+
+    let width = engine.cols * CELL_WIDTH;
+    let height = engine.rows * CELL_HEIGHT;
+
+    let mut output = String::new();
+    output.push_str(&format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"monospace\" font-size=\"14\">\n", width, height));
+    output.push_str(&format!("<rect width=\"{}\" height=\"{}\" fill=\"#0f0f23\"/>\n", width, height));
+
+    for cell in engine.cells() {
+        let style = annotations.styles[cell.index];
+        if style == Style::Empty {
+            continue;
+        }
+
+        let x = (cell.index % engine.cols) * CELL_WIDTH;
+        let y = (cell.index / engine.cols) * CELL_HEIGHT;
+
+        output.push_str("<g>");
+        if let Some(value) = annotations.gears.get(&cell.index) {
+            output.push_str(&format!("<title>gear ratio {}</title>", value));
+        }
+        output.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" fill-opacity=\"0.3\"/>", x, y, CELL_WIDTH, CELL_HEIGHT, fill(style)));
+        output.push_str(&format!("<text x=\"{}\" y=\"{}\" fill=\"{}\">{}</text>", x + 1, y + CELL_HEIGHT - 4, fill(style), escape(engine.value(cell))));
+        output.push_str("</g>\n");
+    }
+
+    output.push_str("</svg>\n");
+
+    output
+}
+
+pub fn write_svg(engine: &Engine, neighborhood: &Neighborhood, rule: &GearRule, filename: String) -> Result<(), Error> {
+    let path = Path::new(&filename);
+    fs::write(path, svg(engine, neighborhood, rule))
+}
+
+fn annotations(engine: &Engine, neighborhood: &Neighborhood, rule: &GearRule) -> Annotations {
+    let labeling = labeling(engine);
+
+    let offsets = neighborhood.offsets();
+
+    let mut part = vec![false; labeling.values.len()];
+    let mut gears = BTreeMap::new();
+
+    engine.cells()
+        .filter(|cell| symbol(engine.value(*cell)))
+        .for_each(|cell| {
+            let ids = adjacent_ids(cell, &labeling, engine, &offsets);
+            for id in &ids {
+                part[*id as usize] = true;
+            }
+
+            if rule.gear(engine.value(cell)) && rule.count.matches(ids.len()) {
+                let values: Vec<u32> = ids.iter()
+                    .map(|id| labeling.values[*id as usize])
+                    .collect();
+                gears.insert(cell.index, rule.combine.apply(&values));
+            }
+        });
+
+    let styles = engine.cells()
+        .map(|cell| {
+            let id = labeling.labels[cell.index];
+            if id != NO_NUMBER {
+                return if part[id as usize] { Style::PartNumber } else { Style::OtherNumber };
+            }
+            if gears.contains_key(&cell.index) {
+                return Style::Gear;
+            }
+            if symbol(engine.value(cell)) {
+                return Style::Symbol;
+            }
+            Style::Empty
+        })
+        .collect();
+
+    Annotations { styles, gears }
+}
+
+fn color(style: Style) -> &'static str {
+    match style {
+        Style::PartNumber => GREEN,
+        Style::OtherNumber => RED,
+        Style::Gear => YELLOW,
+        Style::Symbol => CYAN,
+        Style::Empty => GREY,
+    }
+}
+
+fn fill(style: Style) -> &'static str {
+    match style {
+        Style::PartNumber => "#00cc00",
+        Style::OtherNumber => "#ff3333",
+        Style::Gear => "#ffff66",
+        Style::Symbol => "#33cccc",
+        Style::Empty => "#666666",
+    }
+}
+
+fn escape(c: char) -> String {
+    match c {
+        '&' => "&amp;".to_string(),
+        '<' => "&lt;".to_string(),
+        '>' => "&gt;".to_string(),
+        _ => printable(c).to_string(),
+    }
+}
+
+// control characters would reach the terminal as is, and are not allowed in XML even as references
+fn printable(c: char) -> char {
+    if c.is_control() { char::REPLACEMENT_CHARACTER } else { c }
+}
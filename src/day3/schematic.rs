@@ -0,0 +1,251 @@
+use super::{symbol, Edges, Engine, GearRule, GridError, Neighborhood, Number, NO_NUMBER};
+
+// editable schematic keeping the part number sum and the gear sum up to date
+pub struct Schematic {
+    engine: Engine,
+    offsets: Vec<(isize, isize)>,
+    rule: GearRule,
+    // number id of each digit cell, NO_NUMBER elsewhere
+    labels: Vec<u32>,
+    // numbers by id, None once removed by an edit
+    numbers: Vec<Option<Number>>,
+    // digit runs too long for a u32, by id: they stay numbers but are never parts nor gear numbers
+    oversized: Vec<bool>,
+    part_sum: u64,
+    // exact total of the gear values, which saturate one by one, so that edits can subtract them
+    gear_sum: u128,
+}
+
+impl Schematic {
+    pub fn new(engine: Engine, neighborhood: &Neighborhood, rule: &GearRule) -> Schematic {
+        let mut schematic = Schematic {
+            labels: vec![NO_NUMBER; engine.grid.len()],
+            numbers: Vec::new(),
+            oversized: Vec::new(),
+            engine,
+            offsets: neighborhood.offsets(),
+            rule: rule.clone(),
+            part_sum: 0,
+            gear_sum: 0,
+        };
+
+        // parsed like the edits, so that runs too long for a u32 are flagged rather than fatal
+        if schematic.engine.cols > 0 {
+            for row in 0..schematic.engine.rows {
+                schematic.relabel(row, 0, schematic.engine.cols - 1);
+            }
+        }
+
+        schematic.part_sum = (0..schematic.numbers.len())
+            .map(|id| schematic.part_value(id as u32))
+            .sum();
+
+        schematic.gear_sum = (0..schematic.engine.grid.len())
+            .map(|index| schematic.gear_value(index) as u128)
+            .sum();
+
+        schematic
+    }
+
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    pub fn part_number_sum(&self) -> u64 {
+        self.part_sum
+    }
+
+    // saturates at u64::MAX like gear_rule_sums
+    pub fn gear_sum(&self) -> u64 {
+        self.gear_sum.min(u64::MAX as u128) as u64
+    }
+
+    // replace a cell, only revisiting the numbers and gears around it
+    pub fn set_cell(&mut self, row: usize, col: usize, c: char) -> Result<(), GridError> {
+        if row >= self.engine.rows || col >= self.engine.cols {
+            return Err(GridError::OutOfGrid { row: row + 1, col: col + 1 });
+        }
+        if !c.is_ascii() {
+            return Err(GridError::NonAscii { row: row + 1, col: col + 1 });
+        }
+
+        let index = self.engine.cell(row, col).index;
+        if self.engine.grid[index] == c as u8 {
+            return Ok(());
+        }
+
+        // numbers overlapping the cell or touching it on the same row get rebuilt
+        let removed = self.row_ids(row, col.saturating_sub(1), (col + 1).min(self.engine.cols - 1));
+
+        let start_col = removed.iter()
+            .map(|id| self.number(*id).start_col)
+            .fold(col, usize::min);
+        let end_col = removed.iter()
+            .map(|id| self.number(*id).end_col)
+            .fold(col, usize::max);
+
+        // any gear whose numbers may change sees the rebuilt columns
+        let mut gears = vec![index];
+        for region_col in start_col..=end_col {
+            gears.extend(self.neighbors(row, region_col));
+        }
+        gears.sort();
+        gears.dedup();
+
+        let mut parts = removed.clone();
+        parts.extend(self.ids_seeing(index));
+        parts.sort();
+        parts.dedup();
+
+        for gear in &gears {
+            self.gear_sum -= self.gear_value(*gear) as u128;
+        }
+        for id in &parts {
+            self.part_sum -= self.part_value(*id);
+        }
+
+        self.engine.grid[index] = c as u8;
+
+        for id in removed {
+            self.remove_number(id);
+        }
+
+        let first_new_id = self.numbers.len() as u32;
+        self.relabel(row, start_col, end_col);
+
+        let mut parts: Vec<u32> = (first_new_id..self.numbers.len() as u32)
+            .collect();
+        parts.extend(self.ids_seeing(index));
+        parts.sort();
+        parts.dedup();
+
+        for gear in &gears {
+            self.gear_sum += self.gear_value(*gear) as u128;
+        }
+        for id in &parts {
+            self.part_sum += self.part_value(*id);
+        }
+
+        Ok(())
+    }
+
+    fn number(&self, id: u32) -> &Number {
+        self.numbers[id as usize]
+            .as_ref()
+            .expect("number was removed")
+    }
+
+    fn add_number(&mut self, number: Number, oversized: bool) {
+        let id = self.numbers.len() as u32;
+        for col in number.start_col..=number.end_col {
+            self.labels[number.row * self.engine.cols + col] = id;
+        }
+        self.numbers.push(Some(number));
+        self.oversized.push(oversized);
+    }
+
+    fn remove_number(&mut self, id: u32) {
+        let number = self.numbers[id as usize]
+            .take()
+            .expect("number was removed");
+        for col in number.start_col..=number.end_col {
+            self.labels[number.row * self.engine.cols + col] = NO_NUMBER;
+        }
+    }
+
+    // label the digit runs between start_col and end_col with new ids
+    fn relabel(&mut self, row: usize, start_col: usize, end_col: usize) {
+        let mut col = start_col;
+        while col <= end_col {
+            let line = self.engine.line(row);
+            if !line[col].is_ascii_digit() {
+                col += 1;
+                continue;
+            }
+
+            let number_start = col;
+            while col <= end_col && line[col].is_ascii_digit() {
+                col += 1;
+            }
+
+            let value = std::str::from_utf8(&line[number_start..col])
+                .unwrap()
+                .parse::<u32>();
+
+            let number = Number { value: *value.as_ref().unwrap_or(&0), row, start_col: number_start, end_col: col - 1 };
+            self.add_number(number, value.is_err());
+        }
+    }
+
+    // distinct ids of the numbers in a range of columns of a row
+    fn row_ids(&self, row: usize, start_col: usize, end_col: usize) -> Vec<u32> {
+        let mut ids: Vec<u32> = (start_col..=end_col)
+            .map(|col| self.labels[self.engine.cell(row, col).index])
+            .filter(|id| *id != NO_NUMBER)
+            .collect();
+        ids.dedup();
+        ids
+    }
+
+    // cells in the neighborhood of a cell
+    fn neighbors(&self, row: usize, col: usize) -> Vec<usize> {
+        self.offsets.iter()
+            .filter_map(|(row_offset, col_offset)| self.shift(row, col, *row_offset, *col_offset))
+            .collect()
+    }
+
+    // distinct ids of the numbers having the cell in their neighborhood
+    fn ids_seeing(&self, index: usize) -> Vec<u32> {
+        let row = index / self.engine.cols;
+        let col = index % self.engine.cols;
+
+        let mut ids = Vec::new();
+        for (row_offset, col_offset) in &self.offsets {
+            if let Some(digit) = self.shift(row, col, -row_offset, -col_offset) {
+                let id = self.labels[digit];
+                if id != NO_NUMBER && !ids.contains(&id) {
+                    ids.push(id);
+                }
+            }
+        }
+        ids
+    }
+
+    fn shift(&self, row: usize, col: usize, row_offset: isize, col_offset: isize) -> Option<usize> {
+        self.engine.shift(row, col, row_offset, col_offset, Edges::Clip)
+            .map(|cell| cell.index)
+    }
+
+    // value of the number when a symbol is in its neighborhood, 0 otherwise
+    fn part_value(&self, id: u32) -> u64 {
+        let number = match &self.numbers[id as usize] {
+            Some(number) if !self.oversized[id as usize] => number,
+            _ => return 0,
+        };
+
+        let part = (number.start_col..=number.end_col)
+            .flat_map(|col| self.neighbors(number.row, col))
+            .any(|index| symbol(self.engine.grid[index] as char));
+
+        if part { number.value as u64 } else { 0 }
+    }
+
+    // value of the cell according to the gear rule, 0 when it is not a gear
+    fn gear_value(&self, index: usize) -> u64 {
+        if !self.rule.gear(self.engine.grid[index] as char) {
+            return 0;
+        }
+
+        let values: Vec<u32> = self.ids_seeing(index)
+            .iter()
+            .filter(|id| !self.oversized[**id as usize])
+            .map(|id| self.number(*id).value)
+            .collect();
+
+        if !self.rule.count.matches(values.len()) {
+            return 0;
+        }
+
+        self.rule.combine.apply(&values)
+    }
+}
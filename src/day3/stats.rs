@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+use super::{adjacent_cells_map, adjacent_numbers_map, numbers, symbol, Edges, Engine, Neighborhood, Number};
+
+// where a number is in the schematic, rows and columns are numbered from 1 as in GridError
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub struct Location {
+    pub value: u32,
+    pub row: usize,
+    pub col: usize,
+}
+
+pub struct Statistics {
+    // count of each symbol type
+    symbols: BTreeMap<char, usize>,
+    // per symbol type, how many symbols touch a given count of numbers
+    touches: BTreeMap<char, BTreeMap<usize, usize>>,
+    // numbers touching 2 symbols or more, with their symbol count
+    shared_numbers: Vec<(Location, usize)>,
+    // part numbers linked through shared symbols, largest first
+    components: Vec<Vec<Location>>,
+}
+
+pub fn statistics(engine: &Engine, neighborhood: &Neighborhood) -> Statistics {
+    let numbers = numbers(engine);
+
+    let adjacent_cells = adjacent_cells_map(&numbers, engine, neighborhood, Edges::Clip);
+
+    let adjacent_numbers = adjacent_numbers_map(&adjacent_cells);
+
+    let mut symbols = BTreeMap::new();
+    let mut touches: BTreeMap<char, BTreeMap<usize, usize>> = BTreeMap::new();
+
+    engine.cells()
+        .filter(|cell| symbol(engine.value(*cell)))
+        .for_each(|cell| {
+            let count = adjacent_numbers.get(&cell)
+                .map_or(0, Vec::len);
+            *symbols.entry(engine.value(cell)).or_insert(0) += 1;
+            *touches.entry(engine.value(cell))
+                .or_default()
+                .entry(count)
+                .or_insert(0) += 1;
+        });
+
+    let shared_numbers = numbers.iter()
+        .zip(adjacent_cells.iter())
+        .filter(|(_, cells)| cells.len() > 1)
+        .map(|(number, cells)| (location(number), cells.len()))
+        .collect();
+
+    // union the numbers around each symbol
+    let mut parents: Vec<usize> = (0..numbers.len()).collect();
+    let mut cells: Vec<_> = adjacent_numbers.iter().collect();
+    cells.sort_by_key(|(cell, _)| cell.index);
+    for (_, ids) in cells {
+        for id in &ids[1..] {
+            let root = find(&mut parents, ids[0]);
+            let other = find(&mut parents, *id);
+            parents[other] = root;
+        }
+    }
+
+    let mut components: BTreeMap<usize, Vec<Location>> = BTreeMap::new();
+    for (id, number) in numbers.iter().enumerate() {
+        if adjacent_cells[id].is_empty() {
+            continue;
+        }
+        let root = find(&mut parents, id);
+        components.entry(root)
+            .or_default()
+            .push(location(number));
+    }
+
+    let mut components: Vec<Vec<Location>> = components.into_values()
+        .collect();
+    components.sort_by_key(|component| std::cmp::Reverse(component.len()));
+
+    Statistics { symbols, touches, shared_numbers, components }
+}
+
+impl Statistics {
+    pub fn symbols(&self) -> &BTreeMap<char, usize> {
+        &self.symbols
+    }
+
+    pub fn touches(&self) -> &BTreeMap<char, BTreeMap<usize, usize>> {
+        &self.touches
+    }
+
+    pub fn shared_numbers(&self) -> &[(Location, usize)] {
+        &self.shared_numbers
+    }
+
+    pub fn components(&self) -> &[Vec<Location>] {
+        &self.components
+    }
+
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+
+        report.push_str("symbols\n");
+        for (symbol, count) in &self.symbols {
+            report.push_str(&format!("  {} {}\n", symbol, count));
+        }
+
+        report.push_str("numbers touched by each symbol\n");
+        for (symbol, histogram) in &self.touches {
+            let counts: Vec<String> = histogram.iter()
+                .map(|(numbers, symbols)| format!("{} x {}", symbols, numbers))
+                .collect();
+            report.push_str(&format!("  {} {}\n", symbol, counts.join(", ")));
+        }
+
+        report.push_str("numbers touching several symbols\n");
+        for (location, symbols) in &self.shared_numbers {
+            report.push_str(&format!("  {} at ({}, {}) touches {}\n", location.value, location.row, location.col, symbols));
+        }
+
+        let part_numbers: usize = self.components.iter()
+            .map(Vec::len)
+            .sum();
+        report.push_str(&format!("{} components of {} part numbers\n", self.components.len(), part_numbers));
+        if let Some(largest) = self.components.first() {
+            let values: Vec<String> = largest.iter()
+                .map(|location| location.value.to_string())
+                .collect();
+            report.push_str(&format!("  largest: {}\n", values.join(" ")));
+        }
+
+        report
+    }
+
+    pub fn json(&self) -> String {
+        let symbols: Vec<String> = self.symbols.iter()
+            .map(|(symbol, count)| format!("{}:{}", json_char(*symbol), count))
+            .collect();
+
+        let touches: Vec<String> = self.touches.iter()
+            .map(|(symbol, histogram)| {
+                let counts: Vec<String> = histogram.iter()
+                    .map(|(numbers, symbols)| format!("\"{}\":{}", numbers, symbols))
+                    .collect();
+                format!("{}:{{{}}}", json_char(*symbol), counts.join(","))
+            })
+            .collect();
+
+        let shared_numbers: Vec<String> = self.shared_numbers.iter()
+            .map(|(location, symbols)| format!("{{{},\"symbols\":{}}}", json_location(location), symbols))
+            .collect();
+
+        let components: Vec<String> = self.components.iter()
+            .map(|component| {
+                let locations: Vec<String> = component.iter()
+                    .map(|location| format!("{{{}}}", json_location(location)))
+                    .collect();
+                format!("[{}]", locations.join(","))
+            })
+            .collect();
+
+        format!("{{\"symbols\":{{{}}},\"touches\":{{{}}},\"shared_numbers\":[{}],\"components\":[{}]}}",
+            symbols.join(","), touches.join(","), shared_numbers.join(","), components.join(","))
+    }
+}
+
+fn location(number: &Number) -> Location {
+    Location { value: number.value, row: number.row + 1, col: number.start_col + 1 }
+}
+
+fn find(parents: &mut [usize], id: usize) -> usize {
+    let mut root = id;
+    while parents[root] != root {
+        root = parents[root];
+    }
+
+    // path compression
+    let mut id = id;
+    while parents[id] != root {
+        let parent = parents[id];
+        parents[id] = root;
+        id = parent;
+    }
+
+    root
+}
+
+fn json_location(location: &Location) -> String {
+    format!("\"value\":{},\"row\":{},\"col\":{}", location.value, location.row, location.col)
+}
+
+fn json_char(c: char) -> String {
+    match c {
+        '"' => "\"\\\"\"".to_string(),
+        '\\' => "\"\\\\\"".to_string(),
+        c if c.is_control() => format!("\"\\u{:04x}\"", c as u32),
+        _ => format!("\"{}\"", c),
+    }
+}
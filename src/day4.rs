@@ -1,84 +1,667 @@
-use std::collections::HashSet;
-use std::fs::File;
+use std::collections::{BTreeMap, HashSet};
+use std::collections::btree_map::Entry;
+use std::fmt;
+use std::fs::{self, File};
+use std::ops::Bound::{Excluded, Included};
 use std::path::Path;
-use std::io::{BufRead, BufReader, Error};
+use std::io::{BufRead, BufReader, Error, ErrorKind};
 
-struct Card {
-    winning: HashSet<u32>,
-    hand: Vec<u32>,
+use crate::random::Random;
+
+// numbers below BITS fit in a bitset
+const BITS: u32 = 128;
+
+pub struct Card {
+    id: u32,
+    winning: Numbers,
+    hand: Numbers,
+}
+
+enum Numbers {
+    // one bit per number, when they are small and distinct
+    Bits(u128),
+    Hashed(Vec<u32>, HashSet<u32>),
+}
+
+// how the numbers of a card are stored
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum Matching {
+    // bitsets whenever the numbers fit, hash sets otherwise
+    Bitset,
+    Hashed,
+}
+
+// what to do with copies won past the last card
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum Cascade {
+    // drop them, the puzzle promises it never happens
+    Clip,
+    // reject the input
+    Error,
+    // keep them as virtual cards, which win nothing
+    Extend,
+}
+
+// cards are referred to by their id
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum CascadeError {
+    PastLastCard { card: u32, last_copy: u32, last_card: u32 },
+    MissingCard { card: u32, copy: u32 },
+    DuplicateCard { card: u32 },
+    Overflow { card: u32 },
+    VirtualCardLimit { card: u32, limit: u32 },
+    // a simulation without games or without cards
+    NoCards,
+}
+
+// virtual cards kept by Cascade::Extend, past it the input is rejected
+const VIRTUAL_CARD_LIMIT: u32 = 1 << 20;
+
+impl fmt::Display for CascadeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CascadeError::PastLastCard { card, last_copy, last_card } =>
+                write!(f, "card {} wins copies up to card {}, past the last card {}", card, last_copy, last_card),
+            CascadeError::MissingCard { card, copy } =>
+                write!(f, "card {} wins a copy of the missing card {}", card, copy),
+            CascadeError::DuplicateCard { card } =>
+                write!(f, "card {} appears more than once", card),
+            CascadeError::Overflow { card } =>
+                write!(f, "card count overflows at card {}", card),
+            CascadeError::VirtualCardLimit { card, limit } =>
+                write!(f, "card {} wins copies of more than {} virtual cards", card, limit),
+            CascadeError::NoCards =>
+                write!(f, "no card to simulate"),
+        }
+    }
+}
+
+// how a card ended up with its copies
+#[derive(Debug)]
+pub struct CardTrace {
+    pub id: u32,
+    // false for a virtual card, added by Cascade::Extend
+    pub original: bool,
+    pub matches: usize,
+    // original and copies
+    pub copies: u64,
+    // earlier cards giving copies, with the number of copies each
+    pub from: Vec<(u32, u64)>,
+    // running total of cards, up to this one
+    pub total: u64,
+}
+
+// random cards draw distinct winning numbers and distinct numbers in hand from 1..=max_value
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct CardShape {
+    winning: usize,
+    hand: usize,
+    max_value: u32,
+}
+
+#[derive(Debug)]
+pub struct Summary {
+    pub mean: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub median: f64,
+    pub max: f64,
+}
+
+#[derive(Debug)]
+pub struct Simulation {
+    // per card, simulated against analytical
+    pub mean_matches: f64,
+    pub expected_matches: f64,
+    // probability of each number of matches per card, simulated against analytical
+    pub match_frequencies: Vec<f64>,
+    pub match_distribution: Vec<f64>,
+    // per game
+    pub points: Summary,
+    pub expected_points: f64,
+    pub card_counts: Summary,
+}
+
+// inconsistencies in the card ids, in input order
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum CardIssue {
+    // ids strictly between after and next are missing
+    Gap { after: u32, next: u32 },
+    Duplicate { id: u32 },
+    OutOfOrder { id: u32, previous: u32 },
+}
+
+impl std::error::Error for CascadeError {}
+
+impl From<CascadeError> for Error {
+    fn from(error: CascadeError) -> Error {
+        Error::new(ErrorKind::InvalidData, error)
+    }
+}
+
+// how a card scores, given its count of winning numbers
+pub trait ScoringRule {
+    // points won by the card, doubling by default
+    fn points(&self, matches: usize) -> u64 {
+        Doubling.points(matches)
+    }
+
+    // number of following cards copied, one per match by default
+    fn copies(&self, matches: usize) -> usize {
+        matches
+    }
+}
+
+// 1 point for the first match, then doubled for each other one
+pub struct Doubling;
+
+// 1 point per match
+pub struct Linear;
+
+// 1, 2, 3, 5, 8, ... points
+pub struct Fibonacci;
+
+// any winning card copies the next `window` cards
+pub struct CopiesOfNext {
+    window: usize,
+}
+
+impl ScoringRule for Doubling {
+    fn points(&self, matches: usize) -> u64 {
+        if matches == 0 {
+            return 0;
+        }
+
+        2u64.saturating_pow(matches as u32 - 1)
+    }
+}
+
+impl ScoringRule for Linear {
+    fn points(&self, matches: usize) -> u64 {
+        matches as u64
+    }
 }
 
-pub fn card_point_sum(filename: String) -> Result<u32, Error>
+impl ScoringRule for Fibonacci {
+    fn points(&self, matches: usize) -> u64 {
+        if matches == 0 {
+            return 0;
+        }
+
+        let (mut previous, mut current) = (1u64, 1u64);
+        for _ in 1..matches {
+            (previous, current) = (current, previous.saturating_add(current));
+        }
+        current
+    }
+}
+
+impl CopiesOfNext {
+    pub fn new(window: usize) -> CopiesOfNext {
+        CopiesOfNext { window }
+    }
+}
+
+impl ScoringRule for CopiesOfNext {
+    fn copies(&self, matches: usize) -> usize {
+        if matches == 0 {
+            return 0;
+        }
+
+        self.window
+    }
+}
+
+pub fn card_point_sum(filename: String) -> Result<u64, Error>
+{
+    card_point_sum_with_rule(filename, &Doubling)
+}
+
+pub fn card_point_sum_with_rule(filename: String, rule: &dyn ScoringRule) -> Result<u64, Error>
 {
     let sum = parse_cards(filename)?
         .iter()
-        .map(points)
-        .sum();
+        .map(|card| rule.points(winning_cards(card)))
+        .fold(0, u64::saturating_add);
 
     Ok(sum)
 }
 
-pub fn card_count(filename: String) -> Result<u32, Error>
+pub fn card_count(filename: String) -> Result<u64, Error>
+{
+    card_count_with(filename, Cascade::Clip)
+}
+
+pub fn card_count_with(filename: String, cascade: Cascade) -> Result<u64, Error>
+{
+    card_count_with_rule(filename, &Doubling, cascade)
+}
+
+pub fn card_count_with_rule(filename: String, rule: &dyn ScoringRule, cascade: Cascade) -> Result<u64, Error>
+{
+    let cards = parse_cards(filename)?;
+
+    let count = cascade_count(&cards, rule, cascade)?;
+
+    Ok(count)
+}
+
+pub fn card_issues(filename: String) -> Result<Vec<CardIssue>, Error>
 {
     let cards = parse_cards(filename)?;
 
-    // create an array of size cards.len()
-    let mut counts = vec![1; cards.len()];
+    let mut issues = Vec::new();
 
+    let mut seen = HashSet::new();
     for (i, card) in cards.iter().enumerate() {
-        let winning = winning_cards(card);
-        for j in i+1..=i+winning {
-            counts[j] += counts[i];
+        if !seen.insert(card.id) {
+            issues.push(CardIssue::Duplicate { id: card.id });
+        }
+        if i > 0 && card.id < cards[i - 1].id {
+            issues.push(CardIssue::OutOfOrder { id: card.id, previous: cards[i - 1].id });
+        }
+    }
+
+    let mut ids: Vec<u32> = seen.into_iter()
+        .collect();
+    ids.sort();
+
+    // ids start at 1
+    let mut previous = 0;
+    for id in ids {
+        if id > previous + 1 {
+            issues.push(CardIssue::Gap { after: previous, next: id });
+        }
+        previous = id;
+    }
+
+    Ok(issues)
+}
+
+pub fn card_count_trace(filename: String, rule: &dyn ScoringRule, cascade: Cascade) -> Result<Vec<CardTrace>, Error>
+{
+    let cards = parse_cards(filename)?;
+
+    let traces = cascade_trace(&cards, rule, cascade)?;
+
+    Ok(traces)
+}
+
+pub fn trace_table(traces: &[CardTrace]) -> String {
+    let mut table = String::from(" card matches  copies   total  from\n");
+
+    for trace in traces {
+        let from: Vec<String> = trace.from.iter()
+            .map(|(card, copies)| format!("{} x{}", card, copies))
+            .collect();
+        let id = if trace.original { trace.id.to_string() } else { format!("({})", trace.id) };
+        let line = format!("{:>5} {:>7} {:>7} {:>7}  {}", id, trace.matches, trace.copies, trace.total, from.join(", "));
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+
+    table
+}
+
+pub fn trace_json(traces: &[CardTrace]) -> String {
+    let cards: Vec<String> = traces.iter()
+        .map(|trace| {
+            let from: Vec<String> = trace.from.iter()
+                .map(|(card, copies)| format!("{{\"card\":{},\"copies\":{}}}", card, copies))
+                .collect();
+            format!("{{\"card\":{},\"original\":{},\"matches\":{},\"copies\":{},\"from\":[{}],\"total\":{}}}",
+                trace.id, trace.original, trace.matches, trace.copies, from.join(","), trace.total)
+        })
+        .collect();
+
+    format!("[{}]", cards.join(","))
+}
+
+fn cascade_count(cards: &[Card], rule: &dyn ScoringRule, cascade: Cascade) -> Result<u64, CascadeError> {
+    let traces = cascade_trace(cards, rule, cascade)?;
+
+    let count = traces.last()
+        .map_or(0, |trace| trace.total);
+
+    Ok(count)
+}
+
+// copies are won by id, so the order of the cards does not matter
+fn cascade_trace(cards: &[Card], rule: &dyn ScoringRule, cascade: Cascade) -> Result<Vec<CardTrace>, CascadeError> {
+    let mut matches: BTreeMap<u32, usize> = BTreeMap::new();
+    for card in cards {
+        if matches.insert(card.id, winning_cards(card)).is_some() {
+            return Err(CascadeError::DuplicateCard { card: card.id });
         }
     }
 
-    // return the sum of elements in counts array
-    let sum = counts.iter()
+    let last_card = match matches.keys().next_back() {
+        Some(id) => *id,
+        None => return Ok(Vec::new()),
+    };
+
+    // one original of each card
+    let mut counts: BTreeMap<u32, u64> = matches.keys()
+        .map(|id| (*id, 1))
+        .collect();
+
+    // earlier cards giving copies of each card
+    let mut from: BTreeMap<u32, Vec<(u32, u64)>> = BTreeMap::new();
+
+    let mut traces = Vec::new();
+    let mut total = 0u64;
+    let mut virtual_cards = 0u32;
+
+    // virtual cards are added to counts, and never win
+    let mut next = counts.keys().next().copied();
+    while let Some(id) = next {
+        let count = counts[&id];
+        let card_matches = matches.get(&id)
+            .copied()
+            .unwrap_or(0);
+        let last_copy = u32::try_from(rule.copies(card_matches))
+            .ok()
+            .and_then(|copies| id.checked_add(copies))
+            .ok_or(CascadeError::Overflow { card: id })?;
+
+        if last_copy > last_card && cascade == Cascade::Error {
+            return Err(CascadeError::PastLastCard { card: id, last_copy, last_card });
+        }
+
+        // only existing cards are visited, unless the missing ones become virtual cards
+        if cascade == Cascade::Extend {
+            for copy in id+1..=last_copy {
+                if let Entry::Vacant(entry) = counts.entry(copy) {
+                    virtual_cards += 1;
+                    if virtual_cards > VIRTUAL_CARD_LIMIT {
+                        return Err(CascadeError::VirtualCardLimit { card: id, limit: VIRTUAL_CARD_LIMIT });
+                    }
+                    entry.insert(0);
+                }
+            }
+        }
+
+        // next copy expected when none is missing, in u64 so that it can pass u32::MAX
+        let mut expected = id as u64 + 1;
+        for (copy, copies) in counts.range_mut((Excluded(id), Included(last_copy))) {
+            if *copy as u64 != expected && cascade == Cascade::Error {
+                return Err(CascadeError::MissingCard { card: id, copy: expected as u32 });
+            }
+            expected = *copy as u64 + 1;
+
+            *copies = copies.checked_add(count)
+                .ok_or(CascadeError::Overflow { card: id })?;
+            from.entry(*copy)
+                .or_default()
+                .push((id, count));
+        }
+        if expected <= last_copy as u64 && cascade == Cascade::Error {
+            return Err(CascadeError::MissingCard { card: id, copy: expected as u32 });
+        }
+
+        total = total.checked_add(count)
+            .ok_or(CascadeError::Overflow { card: id })?;
+
+        traces.push(CardTrace {
+            id,
+            original: matches.contains_key(&id),
+            matches: card_matches,
+            copies: count,
+            from: from.remove(&id).unwrap_or_default(),
+            total,
+        });
+
+        next = counts.range(id+1..)
+            .next()
+            .map(|(id, _)| *id);
+    }
+
+    Ok(traces)
+}
+
+// Monte Carlo estimate of the points and card counts of random games,
+// each game being a list of cards_per_game random cards
+pub fn simulate(shape: &CardShape, cards_per_game: usize, games: usize, rule: &dyn ScoringRule, seed: u64) -> Result<Simulation, CascadeError> {
+    if cards_per_game == 0 || games == 0 {
+        return Err(CascadeError::NoCards);
+    }
+
+    let mut random = Random::new(seed);
+
+    let mut match_counts = vec![0usize; shape.winning.min(shape.hand) + 1];
+    let mut points = Vec::with_capacity(games);
+    let mut card_counts = Vec::with_capacity(games);
+
+    for _ in 0..games {
+        let cards: Vec<Card> = (1..=cards_per_game as u32)
+            .map(|id| random_card(&mut random, shape, id))
+            .collect();
+
+        for card in &cards {
+            match_counts[winning_cards(card)] += 1;
+        }
+
+        points.push(cards.iter()
+            .map(|card| rule.points(winning_cards(card)))
+            .fold(0, u64::saturating_add) as f64);
+        card_counts.push(cascade_count(&cards, rule, Cascade::Clip)? as f64);
+    }
+
+    let total_cards = (games * cards_per_game) as f64;
+    let match_frequencies: Vec<f64> = match_counts.iter()
+        .map(|count| *count as f64 / total_cards)
+        .collect();
+    let mean_matches = match_frequencies.iter()
+        .enumerate()
+        .map(|(matches, frequency)| matches as f64 * frequency)
         .sum();
 
-    Ok(sum)
+    let match_distribution = shape.match_distribution();
+    let expected_points = match_distribution.iter()
+        .enumerate()
+        .map(|(matches, probability)| rule.points(matches) as f64 * probability)
+        .sum::<f64>() * cards_per_game as f64;
+
+    Ok(Simulation {
+        mean_matches,
+        expected_matches: shape.expected_matches(),
+        match_frequencies,
+        match_distribution,
+        points: Summary::new(points),
+        expected_points,
+        card_counts: Summary::new(card_counts),
+    })
+}
+
+impl CardShape {
+    pub fn new(winning: usize, hand: usize, max_value: u32) -> CardShape {
+        assert!(winning <= max_value as usize && hand <= max_value as usize, "not enough distinct values");
+        CardShape { winning, hand, max_value }
+    }
+
+    // mean of the hypergeometric distribution
+    pub fn expected_matches(&self) -> f64 {
+        (self.winning * self.hand) as f64 / self.max_value as f64
+    }
+
+    // probability of each number of matches: C(W, k) C(V - W, H - k) / C(V, H)
+    pub fn match_distribution(&self) -> Vec<f64> {
+        let values = self.max_value as usize;
+        (0..=self.winning.min(self.hand))
+            .map(|matches| {
+                if self.hand - matches > values - self.winning {
+                    return 0.0;
+                }
+                (ln_choose(self.winning, matches)
+                    + ln_choose(values - self.winning, self.hand - matches)
+                    - ln_choose(values, self.hand)).exp()
+            })
+            .collect()
+    }
+}
+
+impl Summary {
+    fn new(mut samples: Vec<f64>) -> Summary {
+        if samples.is_empty() {
+            return Summary { mean: 0.0, std_dev: 0.0, min: 0.0, median: 0.0, max: 0.0 };
+        }
+
+        samples.sort_by(f64::total_cmp);
+
+        let count = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / count;
+        let variance = samples.iter()
+            .map(|sample| (sample - mean) * (sample - mean))
+            .sum::<f64>() / count;
+
+        Summary {
+            mean,
+            std_dev: variance.sqrt(),
+            min: samples[0],
+            median: samples[samples.len() / 2],
+            max: samples[samples.len() - 1],
+        }
+    }
+}
+
+fn random_card(random: &mut Random, shape: &CardShape, id: u32) -> Card {
+    let winning = distinct_numbers(random, shape.winning, shape.max_value);
+    let hand = distinct_numbers(random, shape.hand, shape.max_value);
+
+    Card {
+        id,
+        winning: Numbers::new(winning, Matching::Bitset, false),
+        hand: Numbers::new(hand, Matching::Bitset, true),
+    }
+}
+
+// ln(n! / (k! (n - k)!))
+fn ln_choose(n: usize, k: usize) -> f64 {
+    (1..=k)
+        .map(|i| ((n - k + i) as f64 / i as f64).ln())
+        .sum()
+}
+
+// sum of the winning numbers found on each card
+pub fn total_matches(cards: &[Card]) -> u64 {
+    cards.iter()
+        .map(|card| winning_cards(card) as u64)
+        .sum()
+}
+
+// scratchcards like the puzzle input: 10 winning numbers and 25 numbers in hand, from 1 to 99
+pub fn write_random_cards(filename: String, count: usize, seed: u64) -> Result<(), Error> {
+    let mut random = Random::new(seed);
+
+    let mut text = String::new();
+    for id in 1..=count {
+        let winning: Vec<String> = distinct_numbers(&mut random, 10, 99)
+            .iter()
+            .map(|number| format!("{:2}", number))
+            .collect();
+        let hand: Vec<String> = distinct_numbers(&mut random, 25, 99)
+            .iter()
+            .map(|number| format!("{:2}", number))
+            .collect();
+        text.push_str(&format!("Card {}: {} | {}\n", id, winning.join(" "), hand.join(" ")));
+    }
+
+    let path = Path::new(&filename);
+    fs::write(path, text)
 }
 
 fn parse_cards(filename: String) -> Result<Vec<Card>, Error> {
+    parse_cards_with(filename, Matching::Bitset)
+}
+
+pub fn parse_cards_with(filename: String, matching: Matching) -> Result<Vec<Card>, Error> {
     let path = Path::new(&filename);
     let file = File::open(path)?;
     let buf_reader = BufReader::new(file);
 
     let cards = buf_reader.lines()
-        .flatten()
-        .map(parse_card)
+        .collect::<Result<Vec<String>, Error>>()?
+        .into_iter()
+        .map(|line| parse_card(line, matching))
         .collect();
 
     Ok(cards)
 }
 
-fn parse_card(line: String) -> Card {
+fn parse_card(line: String, matching: Matching) -> Card {
     let tokens: Vec<&str> = line.split(": ").collect();
+    let id = parse_sequence(tokens[0].trim_start_matches("Card"))
+        .next()
+        .expect("Missing card id");
     let tokens: Vec<&str> = tokens[1].split(" | ").collect();
 
-    let winning: HashSet<u32> = parse_sequence(tokens[0]).collect();
-    let hand: Vec<u32> = parse_sequence(tokens[1]).collect();
+    let winning = Numbers::new(parse_sequence(tokens[0]).collect(), matching, false);
+    let hand = Numbers::new(parse_sequence(tokens[1]).collect(), matching, true);
 
-    Card { winning, hand }
+    Card { id, winning, hand }
 }
 
-fn parse_sequence(string: &str) -> impl Iterator<Item=u32> + '_ {
-    string.split_whitespace()
-        .map(|x| x.parse())
-        .map(|x| x.expect("Invalid number format"))
+impl Numbers {
+    // a bitset would count a repeated number in hand only once
+    fn new(values: Vec<u32>, matching: Matching, keep_duplicates: bool) -> Numbers {
+        if matching == Matching::Bitset && values.iter().all(|value| *value < BITS) {
+            let bits = values.iter()
+                .fold(0u128, |bits, value| bits | 1 << value);
+            if !keep_duplicates || bits.count_ones() as usize == values.len() {
+                return Numbers::Bits(bits);
+            }
+        }
+
+        let set = values.iter()
+            .copied()
+            .collect();
+        Numbers::Hashed(values, set)
+    }
+
+    fn contains(&self, value: u32) -> bool {
+        match self {
+            Numbers::Bits(bits) => value < BITS && bits & 1 << value != 0,
+            Numbers::Hashed(_, set) => set.contains(&value),
+        }
+    }
 }
 
-fn points(card: &Card) -> u32 {
-    let winning = winning_cards(card);
-    if winning == 0 {
-        return 0;
+// count numbers in 1..=max, without repetition, with a partial Fisher-Yates shuffle
+fn distinct_numbers(random: &mut Random, count: usize, max: u32) -> Vec<u32> {
+    let mut numbers: Vec<u32> = (1..=max).collect();
+    for i in 0..count {
+        let j = i + random.below((numbers.len() - i) as u64) as usize;
+        numbers.swap(i, j);
     }
+    numbers.truncate(count);
+    numbers
+}
 
-    return 2u32.pow(winning as u32 - 1);
+fn parse_sequence(string: &str) -> impl Iterator<Item=u32> + '_ {
+    string.split_whitespace()
+        .map(|x| x.parse())
+        .map(|x| x.expect("Invalid number format"))
 }
 
 fn winning_cards(card: &Card) -> usize {
-    card.hand.iter()
-        .filter(|x| card.winning.contains(x))
-        .count()
+    match (&card.winning, &card.hand) {
+        (Numbers::Bits(winning), Numbers::Bits(hand)) => (winning & hand).count_ones() as usize,
+        (winning, Numbers::Hashed(hand, _)) => hand.iter()
+            .filter(|x| winning.contains(**x))
+            .count(),
+        // distinct numbers in hand, so each winning number matches at most once
+        (Numbers::Hashed(_, winning), hand) => winning.iter()
+            .filter(|x| hand.contains(**x))
+            .count(),
+    }
 }
\ No newline at end of file
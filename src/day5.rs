@@ -1,267 +1,1078 @@
-use std::collections::VecDeque;
-use std::fs::File;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs;
 use std::path::Path;
-use std::io::{BufRead, BufReader, Error};
+use std::thread;
+use std::io::{Error, ErrorKind};
+pub use crate::interval::{Interval, IntervalSet};
+use crate::random::Random;
+
+const SEED: &str = "seed";
+const LOCATION: &str = "location";
 
 #[derive(Debug)]
 struct Almanac {
     seeds: Vec<u64>,
+    categories: CategoryGraph,
+    issues: Vec<AlmanacIssue>,
+}
+
+// what to do with a malformed almanac
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum Validation {
+    // fix the maps: drop the empty ones, clip the overflowing ones,
+    // and give an overlapped value to the map starting first
+    Lenient,
+    // reject the almanac
+    Strict,
+}
+
+// random almanacs chain `categories` categories from seed to location, each transform
+// cutting 0..magnitude into up to `maps` blocks (fewer when cuts coincide),
+// and mapping the first `coverage` of each block
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct AlmanacShape {
+    categories: usize,
+    maps: usize,
+    magnitude: u64,
+    coverage: f64,
+    seed_pairs: usize,
+}
+
+#[derive(Debug)]
+pub struct Locator {
     transforms: Vec<Transform>,
+    composed: Transform,
+}
+
+// how seeds find their map
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum Lookup {
+    // scan the maps of each transform
+    Linear,
+    // binary search the maps of each transform
+    BinarySearch,
+    // walk the sorted seeds and the maps of the composed transform together
+    Merge,
+}
+
+// maps are referred to by their line in the almanac
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum AlmanacIssue {
+    Overlap { map: String, line: usize, other: usize },
+    ZeroLength { map: String, line: usize },
+    Overflow { map: String, line: usize },
+}
+
+impl fmt::Display for AlmanacIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlmanacIssue::Overlap { map, line, other } =>
+                write!(f, "line {}: {} map overlaps the one on line {}", line, map, other),
+            AlmanacIssue::ZeroLength { map, line } =>
+                write!(f, "line {}: {} map has an empty range", line, map),
+            AlmanacIssue::Overflow { map, line } =>
+                write!(f, "line {}: {} map goes past {}", line, map, u64::MAX),
+        }
+    }
+}
+
+impl std::error::Error for AlmanacIssue {}
+
+impl From<AlmanacIssue> for Error {
+    fn from(issue: AlmanacIssue) -> Error {
+        Error::new(ErrorKind::InvalidData, issue)
+    }
 }
 
+// the almanac does not follow its grammar, lines are numbered from 1
 #[derive(Debug)]
+#[derive(PartialEq)]
+pub enum AlmanacError {
+    Empty,
+    MissingSeeds { line: usize },
+    InvalidHeader { line: usize },
+    InvalidNumber { line: usize, token: String },
+    // a map needs a destination start, a source start and a length
+    MapLength { line: usize, found: usize },
+    MapOutsideBlock { line: usize },
+    NoSeeds { line: usize },
+    // seeds come in pairs of a start and a length
+    OddSeeds { line: usize, count: usize },
+    SeedRangeOverflow { line: usize, start: u64, length: u64 },
+}
+
+impl fmt::Display for AlmanacError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlmanacError::Empty =>
+                write!(f, "almanac is empty"),
+            AlmanacError::MissingSeeds { line } =>
+                write!(f, "line {}: expected the seeds first", line),
+            AlmanacError::InvalidHeader { line } =>
+                write!(f, "line {}: expected a header like \"seed-to-soil map:\"", line),
+            AlmanacError::InvalidNumber { line, token } =>
+                write!(f, "line {}: {:?} is not a number", line, token),
+            AlmanacError::MapLength { line, found } =>
+                write!(f, "line {}: expected 3 numbers in a map, found {}", line, found),
+            AlmanacError::MapOutsideBlock { line } =>
+                write!(f, "line {}: map before any header", line),
+            AlmanacError::NoSeeds { line } =>
+                write!(f, "line {}: no seeds", line),
+            AlmanacError::OddSeeds { line, count } =>
+                write!(f, "line {}: {} seeds, expected pairs of a start and a length", line, count),
+            AlmanacError::SeedRangeOverflow { line, start, length } =>
+                write!(f, "line {}: seeds from {} for {} go past {}", line, start, length, u64::MAX),
+        }
+    }
+}
+
+impl std::error::Error for AlmanacError {}
+
+impl From<AlmanacError> for Error {
+    fn from(error: AlmanacError) -> Error {
+        Error::new(ErrorKind::InvalidData, error)
+    }
+}
+
+// the shape cannot give a valid almanac
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum ShapeError {
+    TooFewCategories { categories: usize },
+    NoMaps,
+    NoMagnitude,
+    NoSeeds,
+    Coverage { coverage: f64 },
+}
+
+impl fmt::Display for ShapeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShapeError::TooFewCategories { categories } =>
+                write!(f, "{} categories, an almanac goes at least from seed to location", categories),
+            ShapeError::NoMaps =>
+                write!(f, "a transform is cut into at least one block"),
+            ShapeError::NoMagnitude =>
+                write!(f, "no value to map"),
+            ShapeError::NoSeeds =>
+                write!(f, "an almanac has seeds"),
+            ShapeError::Coverage { coverage } =>
+                write!(f, "coverage {} is not a fraction", coverage),
+        }
+    }
+}
+
+impl std::error::Error for ShapeError {}
+
+impl From<ShapeError> for Error {
+    fn from(error: ShapeError) -> Error {
+        Error::new(ErrorKind::InvalidInput, error)
+    }
+}
+
+// categories linked by the transforms between them
+#[derive(Debug)]
+struct CategoryGraph {
+    transforms: Vec<Transform>,
+    // indexes of the transforms leaving each category
+    edges: HashMap<String, Vec<usize>>,
+}
+
+// the maps cover every value: the values the almanac leaves out are mapped by identity maps
+#[derive(Debug)]
+#[derive(Clone)]
 struct Transform {
+    source: String,
+    destination: String,
     maps: Vec<CategoryMap>,
 }
 
 #[derive(Debug)]
+#[derive(Clone)]
 struct CategoryMap {
     destination_start: u64,
     source_start: u64,
     range_length: u64,
+    // 0 for the identity maps filling the gaps
+    line: usize,
 }
 
+// the seed reaching the lowest location, with the value of each category on the way
 #[derive(Debug)]
-struct Interval {
-    start: u64,
-    length: u64,
+pub struct Lowest {
+    pub seed: u64,
+    pub chain: Vec<(String, u64)>,
+    // the seed interval containing the seed, in part 2
+    pub seed_interval: Option<Interval>,
 }
 
 pub fn lowest_location(filename: String) -> Result<u64, Error>
 {
-    let almanac = parse_almanac(filename)?;
+    lowest_location_with(filename, Validation::Lenient)
+}
+
+pub fn lowest_location_with(filename: String, validation: Validation) -> Result<u64, Error>
+{
+    let almanac = parse_almanac_with(filename, validation)?;
+    let transforms = almanac.categories.path(SEED, LOCATION)?;
 
     let min = almanac.seeds
         .iter()
-        .map(|seed| location(seed, &almanac.transforms))
+        .map(|seed| location(seed, &transforms))
         .min()
-        .expect("List of seeds is empty");
+        .ok_or_else(no_seeds)?;
 
     Ok(min)
 }
 
-pub fn lowest_location2(filename: String) -> Result<u64, Error>
+// value of the category `to` corresponding to a value of the category `from`
+pub fn map_value(filename: String, from: &str, to: &str, value: u64) -> Result<u64, Error>
 {
     let almanac = parse_almanac(filename)?;
-    let mut seed_intervals: Vec<Interval> = (0..almanac.seeds.len()/2).map(|i| Interval {
-            start: almanac.seeds[2*i],
-            length: almanac.seeds[2*i+1],
-        }).collect();
+    let transforms = almanac.categories.path(from, to)?;
 
-    sort_intervals(&mut seed_intervals);
+    Ok(location(&value, &transforms))
+}
 
-    let location_intervals: Vec<Interval> = almanac.transforms.iter()
+pub fn lowest_location2(filename: String) -> Result<u64, Error>
+{
+    lowest_location2_with(filename, Validation::Lenient)
+}
+
+pub fn lowest_location2_with(filename: String, validation: Validation) -> Result<u64, Error>
+{
+    let almanac = parse_almanac_with(filename, validation)?;
+    let transforms = almanac.categories.path(SEED, LOCATION)?;
+    let seed_intervals = IntervalSet::from(seed_intervals(&almanac.seeds));
+
+    let location_intervals = transforms.into_iter()
         .fold(seed_intervals, transpose);
 
-    let min = location_intervals[0].start;
+    let min = location_intervals.min()
+        .ok_or_else(no_seeds)?;
     Ok(min)
 }
 
-fn parse_almanac(filename: String) -> Result<Almanac, Error>
+// reference for lowest_location2: every seed of every interval goes through the maps
+// as written in the almanac, without the gap filling and fixes of parse_almanac,
+// seeds being dealt to the threads in turn (at least one)
+pub fn lowest_location2_brute_force(filename: String, threads: usize) -> Result<u64, Error>
 {
     let path = Path::new(&filename);
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+    let text = fs::read_to_string(path)?;
+    let (seeds, transforms) = parse_text(&text)?;
+    let categories = CategoryGraph::new(transforms)?;
+    let transforms = categories.path(SEED, LOCATION)?;
+    let seed_intervals = seed_intervals(&seeds);
+
+    let threads = threads.max(1);
+
+    let min = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads as u64)
+            .map(|thread| {
+                let (transforms, seed_intervals) = (&transforms, &seed_intervals);
+                scope.spawn(move || seed_intervals.iter()
+                    .flat_map(|interval| (interval.start.saturating_add(thread)..interval.end()).step_by(threads))
+                    .map(|seed| transforms.iter()
+                        .fold(seed, |value, transform| apply_written_maps(value, &transform.maps)))
+                    .min())
+            })
+            .collect();
+
+        handles.into_iter()
+            .filter_map(|handle| handle.join().expect("Thread panicked"))
+            .min()
+    });
+
+    min.ok_or_else(no_seeds)
+}
+
+// values of each category reached from the seeds, category after category
+pub fn stages(filename: String, seeds: IntervalSet) -> Result<Vec<(String, IntervalSet)>, Error>
+{
+    let almanac = parse_almanac(filename)?;
+    let transforms = almanac.categories.path(SEED, LOCATION)?;
 
-    let mut lines = reader.lines();
+    let mut stages = vec![(SEED.to_string(), seeds)];
+    for transform in transforms {
+        let intervals = transpose(stages[stages.len() - 1].1.clone(), transform);
+        stages.push((transform.destination.clone(), intervals));
+    }
 
-    let seeds = parse_seeds(&mut lines);
+    Ok(stages)
+}
 
-    let mut transforms: Vec<Transform> = Vec::new();
+// location of each seed, in the order of the seeds
+pub fn locations(filename: String, seeds: &[u64], lookup: Lookup) -> Result<Vec<u64>, Error>
+{
+    let locator = locator(filename)?;
+
+    Ok(locator.locations(seeds, lookup))
+}
+
+// the seed-to-location transforms of an almanac, parsed and composed once for many lookups
+pub fn locator(filename: String) -> Result<Locator, Error>
+{
+    let almanac = parse_almanac(filename)?;
+    let transforms = almanac.categories.path(SEED, LOCATION)?;
+
+    let composed = compose(&transforms);
+    let transforms = transforms.into_iter()
+        .cloned()
+        .collect();
+
+    Ok(Locator { transforms, composed })
+}
 
-    loop {
-        let transform = parse_transform(&mut lines);
-        if transform.is_none() {
-            break;
+impl Locator {
+    pub fn locations(&self, seeds: &[u64], lookup: Lookup) -> Vec<u64> {
+        let transforms: Vec<&Transform> = self.transforms.iter()
+            .collect();
+
+        match lookup {
+            Lookup::Linear => seeds.iter()
+                .map(|seed| transforms.iter()
+                    .fold(*seed, |value, transform| apply_transform_linear(value, transform)))
+                .collect(),
+            Lookup::BinarySearch => seeds.iter()
+                .map(|seed| location(seed, &transforms))
+                .collect(),
+            Lookup::Merge => self.composed.lookup_all(seeds),
         }
+    }
+}
+
+// same as lowest_location, with a single lookup in the composed map for each seed
+pub fn lowest_location_composed(filename: String) -> Result<u64, Error>
+{
+    let almanac = parse_almanac(filename)?;
+    let composed = compose(&almanac.categories.path(SEED, LOCATION)?);
+
+    let min = almanac.seeds
+        .iter()
+        .map(|seed| composed.lookup(*seed))
+        .min()
+        .ok_or_else(no_seeds)?;
+
+    Ok(min)
+}
+
+// same as lowest_location2, with a single search in the composed map for each seed interval
+pub fn lowest_location2_composed(filename: String) -> Result<u64, Error>
+{
+    let almanac = parse_almanac(filename)?;
+    let composed = compose(&almanac.categories.path(SEED, LOCATION)?);
+
+    let min = seed_intervals(&almanac.seeds)
+        .iter()
+        .filter_map(|interval| composed.lowest(interval))
+        .min()
+        .ok_or_else(no_seeds)?;
+
+    Ok(min)
+}
+
+pub fn lowest_seed(filename: String) -> Result<Lowest, Error>
+{
+    let almanac = parse_almanac(filename)?;
+    let transforms = almanac.categories.path(SEED, LOCATION)?;
 
-        transforms.push(transform.unwrap());
+    let seed = *almanac.seeds
+        .iter()
+        .min_by_key(|seed| (location(seed, &transforms), **seed))
+        .ok_or_else(no_seeds)?;
+
+    Ok(Lowest { seed, chain: chain(seed, &transforms), seed_interval: None })
+}
+
+pub fn lowest_seed2(filename: String) -> Result<Lowest, Error>
+{
+    let almanac = parse_almanac(filename)?;
+    let transforms = almanac.categories.path(SEED, LOCATION)?;
+    let composed = compose(&transforms);
+
+    let (seed, _, interval) = seed_intervals(&almanac.seeds)
+        .into_iter()
+        .filter_map(|interval| composed.argmin(&interval)
+            .map(|(seed, location)| (seed, location, interval)))
+        .min_by_key(|(seed, location, _)| (*location, *seed))
+        .ok_or_else(no_seeds)?;
+
+    Ok(Lowest { seed, chain: chain(seed, &transforms), seed_interval: Some(interval) })
+}
+
+// "seed 82 (79..93) soil 84 fertilizer 84 ... location 46"
+impl fmt::Display for Lowest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", SEED, self.seed)?;
+        if let Some(interval) = &self.seed_interval {
+            write!(f, " ({}..{})", interval.start, interval.start + interval.length)?;
+        }
+        for (category, value) in &self.chain {
+            write!(f, " {} {}", category, value)?;
+        }
+
+        Ok(())
     }
+}
 
-    let almanac = Almanac { seeds, transforms };
-    Ok(almanac)
+// the seed-to-location map in the almanac format
+pub fn composed_map(filename: String) -> Result<String, Error>
+{
+    let almanac = parse_almanac(filename)?;
+    let composed = compose(&almanac.categories.path(SEED, LOCATION)?);
+
+    Ok(composed.to_string())
 }
 
-fn parse_seeds(mut lines: impl Iterator<Item=Result<String, Error>>) -> Vec<u64>
+// seed intervals ending up in the interval of locations
+pub fn seeds_for_locations(filename: String, locations: Interval) -> Result<IntervalSet, Error>
 {
-    let line = lines.next().unwrap().unwrap();
-    lines.next();
+    let almanac = parse_almanac(filename)?;
+    let transforms = almanac.categories.path(SEED, LOCATION)?;
+
+    let seeds = transforms.iter()
+        .rev()
+        .fold(IntervalSet::from(vec![locations]), |intervals, transform| preimage(&intervals, transform));
+
+    Ok(seeds)
+}
+
+// true when the lowest location of part 2 comes from a seed interval,
+// and no lower location does
+pub fn verify_lowest_location2(filename: String) -> Result<bool, Error>
+{
+    let min = lowest_location2(filename.clone())?;
+
+    let almanac = parse_almanac(filename.clone())?;
+    let seed_intervals = IntervalSet::from(seed_intervals(&almanac.seeds));
+
+    let reached = seeds_for_locations(filename.clone(), Interval { start: min, length: 1 })?;
+    let below = seeds_for_locations(filename, Interval { start: 0, length: min })?;
+
+    let verified = !reached.intersection(&seed_intervals).is_empty()
+        && below.intersection(&seed_intervals).is_empty();
+
+    Ok(verified)
+}
+
+// problems in the maps, in almanac order
+pub fn almanac_issues(filename: String) -> Result<Vec<AlmanacIssue>, Error>
+{
+    let almanac = parse_almanac(filename)?;
+
+    Ok(almanac.issues)
+}
+
+impl AlmanacShape {
+    pub fn new(categories: usize, maps: usize, magnitude: u64, coverage: f64, seed_pairs: usize) -> Result<AlmanacShape, ShapeError> {
+        if categories < 2 {
+            return Err(ShapeError::TooFewCategories { categories });
+        }
+        if maps == 0 {
+            return Err(ShapeError::NoMaps);
+        }
+        if magnitude == 0 {
+            return Err(ShapeError::NoMagnitude);
+        }
+        if seed_pairs == 0 {
+            return Err(ShapeError::NoSeeds);
+        }
+        if !(0.0..=1.0).contains(&coverage) {
+            return Err(ShapeError::Coverage { coverage });
+        }
+
+        Ok(AlmanacShape { categories, maps, magnitude, coverage, seed_pairs })
+    }
+}
+
+pub fn write_random_almanac(filename: String, shape: &AlmanacShape, seed: u64) -> Result<(), Error> {
+    let path = Path::new(&filename);
+    fs::write(path, random_almanac(shape, seed))
+}
 
-    let tokens: Vec<&str> = line.split(": ")
+// valid almanac: no empty, overlapping or overflowing map,
+// but seed ranges after the first one may be empty
+pub fn random_almanac(shape: &AlmanacShape, seed: u64) -> String {
+    let mut random = Random::new(seed);
+    let magnitude = shape.magnitude;
+
+    let seeds: Vec<String> = (0..shape.seed_pairs)
+        .map(|index| {
+            let start = random.below(magnitude);
+            let length = match random.below(4) {
+                0 if index > 0 => 0,
+                _ => 1 + random.below((magnitude - start).min(magnitude / shape.seed_pairs as u64).max(1)),
+            };
+            format!("{} {}", start, length)
+        })
         .collect();
+    let mut text = format!("{}s: {}\n", SEED, seeds.join(" "));
+
+    let categories: Vec<String> = (0..shape.categories)
+        .map(|index| match index {
+            0 => SEED.to_string(),
+            index if index == shape.categories - 1 => LOCATION.to_string(),
+            index => format!("category{}", index),
+        })
+        .collect();
+
+    for pair in categories.windows(2) {
+        text.push_str(&format!("\n{}-to-{} map:\n", pair[0], pair[1]));
+
+        let mut cuts: Vec<u64> = (1..shape.maps)
+            .map(|_| random.below(magnitude))
+            .collect();
+        cuts.extend([0, magnitude]);
+        cuts.sort();
+        cuts.dedup();
+
+        for block in cuts.windows(2) {
+            let block_length = block[1] - block[0];
+            let length = ((block_length as f64 * shape.coverage) as u64).min(block_length);
+            if length == 0 {
+                continue;
+            }
+            let destination = random.below(magnitude - length + 1);
+            text.push_str(&format!("{} {} {}\n", destination, block[0], length));
+        }
+    }
 
-    parse_sequence(tokens[1])
+    text
 }
 
-fn parse_sequence(token: &str) -> Vec<u64>
+fn parse_almanac(filename: String) -> Result<Almanac, Error>
 {
-    token.split(" ")
-        .map(parse_token)
-        .collect()
+    parse_almanac_with(filename, Validation::Lenient)
 }
 
-fn parse_transform(mut lines: impl Iterator<Item=Result<String, Error>>) -> Option<Transform>
+fn parse_almanac_with(filename: String, validation: Validation) -> Result<Almanac, Error>
 {
-    // parse the 1st line containing the map name
-    let mut line = lines.next();
-    if line.is_none() {
-        return None;
+    let path = Path::new(&filename);
+    let text = fs::read_to_string(path)?;
+
+    let (seeds, raw_transforms) = parse_text(&text)?;
+
+    let mut transforms: Vec<Transform> = Vec::new();
+    let mut issues = Vec::new();
+
+    for mut transform in raw_transforms {
+        issues.extend(map_issues(&transform));
+        transform.maps = complete_maps(transform.maps);
+        transforms.push(transform);
+    }
+
+    if validation == Validation::Strict && !issues.is_empty() {
+        return Err(issues.swap_remove(0).into());
     }
 
-    let mut maps = Vec::new();
+    let categories = CategoryGraph::new(transforms)?;
+
+    let almanac = Almanac { seeds, categories, issues };
+    Ok(almanac)
+}
+
+// the maps being sorted, each map is checked against the one reaching furthest before it
+fn map_issues(transform: &Transform) -> Vec<AlmanacIssue> {
+    let map = format!("{}-to-{}", transform.source, transform.destination);
+    let mut issues = Vec::new();
+    let mut furthest: Option<(u64, usize)> = None;
+
+    for category_map in &transform.maps {
+        let line = category_map.line;
+
+        if category_map.range_length == 0 {
+            issues.push(AlmanacIssue::ZeroLength { map: map.clone(), line });
+            continue;
+        }
 
-    loop {
-        line = lines.next();
-        if line.is_none() {
-            break;
+        let source_end = category_map.source_start.checked_add(category_map.range_length);
+        let destination_end = category_map.destination_start.checked_add(category_map.range_length);
+        if source_end.is_none() || destination_end.is_none() {
+            issues.push(AlmanacIssue::Overflow { map: map.clone(), line });
         }
 
-        let line_string = line.unwrap().unwrap();
-        if line_string.is_empty() {
-            break;
+        if let Some((end, other)) = furthest {
+            if category_map.source_start < end {
+                issues.push(AlmanacIssue::Overlap { map: map.clone(), line, other });
+            }
         }
 
-        let category_map = parse_category_map(&line_string);
-        maps.push(category_map);
+        let end = source_end.unwrap_or(u64::MAX);
+        if furthest.is_none_or(|(furthest_end, _)| end > furthest_end) {
+            furthest = Some((end, line));
+        }
     }
 
-    // sort vector by source_start in place
-    maps.sort_by_key(|category_map| category_map.source_start);
+    issues.sort_by_key(|issue| match issue {
+        AlmanacIssue::Overlap { line, .. } | AlmanacIssue::ZeroLength { line, .. } | AlmanacIssue::Overflow { line, .. } => *line,
+    });
 
-    let transform = Transform { maps };
-    Some(transform)
+    issues
 }
 
-fn parse_category_map(line: &str) -> CategoryMap {
-    // parse the 3 tokens in the line
-    let tokens: Vec<&str> = line.split(" ")
-        .collect();
-    let destination_start = parse_token(tokens[0]);
-    let source_start = parse_token(tokens[1]);
-    let range_length = parse_token(tokens[2]);
+impl CategoryGraph {
+    fn new(transforms: Vec<Transform>) -> Result<CategoryGraph, Error> {
+        let mut edges: HashMap<String, Vec<usize>> = HashMap::new();
 
-    CategoryMap {
-        destination_start,
-        source_start,
-        range_length,
+        for (index, transform) in transforms.iter().enumerate() {
+            let leaving = edges.entry(transform.source.clone())
+                .or_default();
+            if leaving.iter().any(|other| transforms[*other].destination == transform.destination) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("{}-to-{} map is defined twice", transform.source, transform.destination)));
+            }
+            leaving.push(index);
+        }
+
+        Ok(CategoryGraph { transforms, edges })
+    }
+
+    // transforms to apply in turn to go from a category to another, the shortest chain
+    fn path(&self, from: &str, to: &str) -> Result<Vec<&Transform>, Error> {
+        // transform reaching each visited category
+        let mut reached_by: HashMap<&str, Option<usize>> = HashMap::new();
+        reached_by.insert(from, None);
+
+        let mut queue = VecDeque::from([from]);
+        while let Some(category) = queue.pop_front() {
+            if category == to {
+                break;
+            }
+
+            for index in self.edges.get(category).into_iter().flatten() {
+                let destination = self.transforms[*index].destination.as_str();
+                if !reached_by.contains_key(destination) {
+                    reached_by.insert(destination, Some(*index));
+                    queue.push_back(destination);
+                }
+            }
+        }
+
+        if !reached_by.contains_key(to) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("no chain of maps from {} to {}", from, to)));
+        }
+
+        let mut path = Vec::new();
+        let mut category = to;
+        while let Some(Some(index)) = reached_by.get(category) {
+            let transform = &self.transforms[*index];
+            path.push(transform);
+            category = transform.source.as_str();
+        }
+        path.reverse();
+
+        Ok(path)
     }
 }
 
-fn parse_token(token: &str) -> u64 {
-    token.parse()
-        .expect("Invalid number format")
+// the seeds, then blocks of maps under their header, the maps of each transform sorted
+fn parse_text(text: &str) -> Result<(Vec<u64>, Vec<Transform>), AlmanacError>
+{
+    let mut lines = content_lines(text);
+
+    let (number, line) = lines.next()
+        .ok_or(AlmanacError::Empty)?;
+    let seeds = line.strip_prefix("seeds:")
+        .ok_or(AlmanacError::MissingSeeds { line: number })?;
+    let seeds = parse_sequence(seeds, number)?;
+
+    if seeds.is_empty() {
+        return Err(AlmanacError::NoSeeds { line: number });
+    }
+    if seeds.len() % 2 == 1 {
+        return Err(AlmanacError::OddSeeds { line: number, count: seeds.len() });
+    }
+    if let Some(pair) = seeds.chunks(2).find(|pair| pair[0].checked_add(pair[1]).is_none()) {
+        return Err(AlmanacError::SeedRangeOverflow { line: number, start: pair[0], length: pair[1] });
+    }
+
+    let mut transforms: Vec<Transform> = Vec::new();
+
+    for (number, line) in lines {
+        if let Some(name) = line.strip_suffix("map:") {
+            let (source, destination) = parse_header(name)
+                .ok_or(AlmanacError::InvalidHeader { line: number })?;
+            transforms.push(Transform { source, destination, maps: Vec::new() });
+            continue;
+        }
+
+        let transform = transforms.last_mut()
+            .ok_or(AlmanacError::MapOutsideBlock { line: number })?;
+        transform.maps.push(parse_category_map(line, number)?);
+    }
+
+    for transform in &mut transforms {
+        // sort vector by source_start in place
+        transform.maps.sort_by_key(|category_map| category_map.source_start);
+    }
+
+    Ok((seeds, transforms))
 }
 
-fn location(seed: &u64, transforms: &Vec<Transform>) -> u64 {
-    transforms.iter()
-        .fold(*seed, apply_transform)
+// numbered lines with something on them, without line endings, comments after '#',
+// or surrounding whitespace
+fn content_lines(text: &str) -> impl Iterator<Item=(usize, &str)> {
+    text.split('\n')
+        .enumerate()
+        .map(|(index, line)| {
+            let line = line.split('#')
+                .next()
+                .unwrap_or_default();
+            (index + 1, line.trim())
+        })
+        .filter(|(_, line)| !line.is_empty())
 }
 
-fn apply_transform(value: u64, transform: &Transform) -> u64 {
-    transform.maps.iter()
-        .find(|category_map| within_range(value, category_map))
-        .map(|category_map| apply_map(value, category_map))
-        .or(Some(value))
-        .unwrap()
+fn parse_sequence(line: &str, number: usize) -> Result<Vec<u64>, AlmanacError>
+{
+    line.split_whitespace()
+        .map(|token| parse_token(token, number))
+        .collect()
 }
 
-fn transpose(interval_vec: Vec<Interval>, transform: &Transform) -> Vec<Interval> {
-    let mut transposed : Vec<Interval> = Vec::new();
+// fill the gaps between the sorted maps with identity maps, up to u64::MAX,
+// after fixing the maps the way Validation::Lenient describes
+fn complete_maps(maps: Vec<CategoryMap>) -> Vec<CategoryMap> {
+    let mut complete = Vec::new();
+    let mut covered_end = 0;
 
-    let interval_id = 0;
-    let mut category_map_id = 0;
+    for mut category_map in maps {
+        category_map.range_length = category_map.range_length
+            .min(u64::MAX - category_map.source_start)
+            .min(u64::MAX - category_map.destination_start);
 
-    let category_maps = &transform.maps;
+        // the part overlapping the maps before belongs to them
+        let source_end = category_map.source_start + category_map.range_length;
+        if category_map.range_length == 0 || source_end <= covered_end {
+            continue;
+        }
+        if category_map.source_start < covered_end {
+            category_map.destination_start += covered_end - category_map.source_start;
+            category_map.source_start = covered_end;
+            category_map.range_length = source_end - covered_end;
+        }
 
-    let mut intervals = VecDeque::from(interval_vec);
+        if category_map.source_start > covered_end {
+            complete.push(identity_map(covered_end, category_map.source_start));
+        }
+        covered_end = source_end;
+        complete.push(category_map);
+    }
 
-    while interval_id < intervals.len() && category_map_id < category_maps.len() {
+    if covered_end < u64::MAX {
+        complete.push(identity_map(covered_end, u64::MAX));
+    }
 
-        let interval_start = intervals[interval_id].start;
-        let interval_length = intervals[interval_id].length;
-        let interval_end = interval_start + interval_length;
+    complete
+}
 
-        let category_start = category_maps[category_map_id].source_start;
-        let category_end = category_start + category_maps[category_map_id].range_length;
+fn identity_map(start: u64, end: u64) -> CategoryMap {
+    CategoryMap {
+        destination_start: start,
+        source_start: start,
+        range_length: end - start,
+        line: 0,
+    }
+}
 
-        // assign the min between interval_end and category_end to left_end
-        let left_end = interval_end.min(category_end);
+impl Transform {
+    // swap the source and destination ranges, a value may then have several images or none
+    fn inverse(&self) -> Transform {
+        let mut maps: Vec<CategoryMap> = self.maps.iter()
+            .map(|category_map| CategoryMap {
+                destination_start: category_map.source_start,
+                source_start: category_map.destination_start,
+                range_length: category_map.range_length,
+                line: category_map.line,
+            })
+            .collect();
+        maps.sort_by_key(|category_map| category_map.source_start);
+
+        Transform {
+            source: self.destination.clone(),
+            destination: self.source.clone(),
+            maps,
+        }
+    }
+}
 
-        if interval_start < category_start {
-            let left_length = left_end - interval_start;
+// a single transform doing all the transforms in turn, from the source of the first one
+// to the destination of the last one
+fn compose(transforms: &[&Transform]) -> Transform {
+    let (first, rest) = transforms.split_first()
+        .expect("No transform to compose");
 
-            transposed.push(Interval {
-                start: interval_start,
-                length: left_length,
-            });
+    let mut composed = Transform {
+        source: first.source.clone(),
+        destination: first.destination.clone(),
+        maps: first.maps.clone(),
+    };
 
-            intervals.pop_front();
+    for transform in rest {
+        composed = composed.then(transform);
+    }
 
-            if interval_end <= category_start {
-                continue;
-            }
+    composed
+}
 
-            let right_length = interval_end - left_end;
-            if right_length == 0 {
-                continue;
+impl Transform {
+    // split each map along the maps of the next transform its destination range falls into
+    fn then(&self, next: &Transform) -> Transform {
+        let mut maps = Vec::new();
+
+        for category_map in &self.maps {
+            let destination_end = category_map.destination_start + category_map.range_length;
+            let mut index = next.position(category_map.destination_start);
+
+            while index < next.maps.len() && next.maps[index].source_start < destination_end {
+                let next_map = &next.maps[index];
+                let start = category_map.destination_start.max(next_map.source_start);
+                let end = destination_end.min(next_map.source_start + next_map.range_length);
+                if start < end {
+                    maps.push(CategoryMap {
+                        destination_start: apply_map(start, next_map),
+                        source_start: start - category_map.destination_start + category_map.source_start,
+                        range_length: end - start,
+                        line: category_map.line,
+                    });
+                }
+                index += 1;
             }
+        }
 
-            intervals.push_front(Interval {
-                start: left_end,
-                length: right_length,
-            });
+        maps.sort_by_key(|category_map| category_map.source_start);
 
-            continue;
+        Transform {
+            source: self.source.clone(),
+            destination: next.destination.clone(),
+            maps,
         }
+    }
 
-        if interval_start < category_end {
-            // assign the max between interval_start and category_start to middle_start
-            let middle_start = interval_start.max(category_start);
-            let middle_length = left_end - middle_start;
-
-            let transpose_start = apply_map(interval_start, &category_maps[category_map_id]);
+    // index of the map containing the value, the maps being sorted and covering every value
+    fn position(&self, value: u64) -> usize {
+        self.maps.partition_point(|category_map| category_map.source_start <= value)
+            .saturating_sub(1)
+    }
 
-            transposed.push(Interval {
-                start: transpose_start,
-                length: middle_length,
-            });
+    fn lookup(&self, value: u64) -> u64 {
+        let category_map = &self.maps[self.position(value)];
+        if within_range(value, category_map) {
+            apply_map(value, category_map)
+        } else {
+            value
+        }
+    }
 
-            intervals.pop_front();
+    // one pass over the maps for the values taken in increasing order
+    fn lookup_all(&self, values: &[u64]) -> Vec<u64> {
+        let mut order: Vec<usize> = (0..values.len()).collect();
+        if !values.is_sorted() {
+            order.sort_by_key(|index| values[*index]);
+        }
 
-            if interval_end <= category_end {
-                continue;
+        let mut destinations = vec![0; values.len()];
+        let mut map_index = 0;
+        for index in order {
+            let value = values[index];
+            while map_index + 1 < self.maps.len() && self.maps[map_index + 1].source_start <= value {
+                map_index += 1;
             }
 
-            let right_length = interval_end - category_end;
+            let category_map = &self.maps[map_index];
+            destinations[index] = if within_range(value, category_map) {
+                apply_map(value, category_map)
+            } else {
+                value
+            };
+        }
 
-            intervals.push_front(Interval {
-                start: category_end,
-                length: right_length,
-            });
+        destinations
+    }
 
-            continue;
+    fn lowest(&self, interval: &Interval) -> Option<u64> {
+        self.argmin(interval)
+            .map(|(_, destination)| destination)
+    }
+
+    // lowest destination value of the interval and the value reaching it,
+    // the lowest of each map being at its start
+    fn argmin(&self, interval: &Interval) -> Option<(u64, u64)> {
+        // an empty interval has no value, even at its start
+        if interval.length == 0 {
+            return None;
+        }
+
+        let interval_end = interval.start + interval.length;
+
+        self.maps[self.position(interval.start)..].iter()
+            .take_while(|category_map| category_map.source_start < interval_end)
+            .filter(|category_map| interval.start < category_map.source_start + category_map.range_length)
+            .map(|category_map| {
+                let value = interval.start.max(category_map.source_start);
+                (value, apply_map(value, category_map))
+            })
+            .min_by_key(|(value, destination)| (*destination, *value))
+    }
+}
+
+// the identity maps are left out, and the contiguous maps are merged
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}-to-{} map:", self.source, self.destination)?;
+
+        let mut merged: Vec<CategoryMap> = Vec::new();
+        for category_map in &self.maps {
+            if let Some(last) = merged.last_mut() {
+                if last.source_start + last.range_length == category_map.source_start
+                    && last.destination_start + last.range_length == category_map.destination_start {
+                    last.range_length += category_map.range_length;
+                    continue;
+                }
+            }
+            merged.push(category_map.clone());
         }
 
-        category_map_id += 1;
+        for category_map in merged.iter().filter(|category_map| category_map.destination_start != category_map.source_start) {
+            writeln!(f, "{} {} {}", category_map.destination_start, category_map.source_start, category_map.range_length)?;
+        }
+
+        Ok(())
+    }
+}
+
+// "seed-to-soil" links the seed category to the soil category
+fn parse_header(name: &str) -> Option<(String, String)> {
+    let (source, destination) = name.trim()
+        .split_once("-to-")?;
+
+    let valid = |category: &str| !category.is_empty() && !category.contains(char::is_whitespace);
+    if !valid(source) || !valid(destination) {
+        return None;
     }
 
-    while ! intervals.is_empty() {
-        let interval_start = intervals[interval_id].start;
-        let interval_length = intervals[interval_id].length;
-        transposed.push(Interval {
-            start: interval_start,
-            length: interval_length,
-        });
-        intervals.pop_front();
+    Some((source.to_string(), destination.to_string()))
+}
+
+fn parse_category_map(line: &str, number: usize) -> Result<CategoryMap, AlmanacError> {
+    let tokens = parse_sequence(line, number)?;
+    if tokens.len() != 3 {
+        return Err(AlmanacError::MapLength { line: number, found: tokens.len() });
     }
 
-    sort_intervals(&mut transposed);
+    Ok(CategoryMap {
+        destination_start: tokens[0],
+        source_start: tokens[1],
+        range_length: tokens[2],
+        line: number,
+    })
+}
+
+fn parse_token(token: &str, number: usize) -> Result<u64, AlmanacError> {
+    token.parse()
+        .map_err(|_| AlmanacError::InvalidNumber { line: number, token: token.to_string() })
+}
+
+fn location(seed: &u64, transforms: &[&Transform]) -> u64 {
+    transforms.iter()
+        .fold(*seed, |value, transform| apply_transform(value, transform))
+}
+
+// value of each destination category, transform after transform
+fn chain(seed: u64, transforms: &[&Transform]) -> Vec<(String, u64)> {
+    let mut value = seed;
+
+    transforms.iter()
+        .map(|transform| {
+            value = apply_transform(value, transform);
+            (transform.destination.clone(), value)
+        })
+        .collect()
+}
+
+fn apply_transform(value: u64, transform: &Transform) -> u64 {
+    transform.lookup(value)
+}
+
+// the first map containing the value, in source order, as Validation::Lenient reads the maps:
+// a map stops where its source or destination would reach u64::MAX
+fn apply_written_maps(value: u64, maps: &[CategoryMap]) -> u64 {
+    maps.iter()
+        .find(|category_map| {
+            let offset = value.wrapping_sub(category_map.source_start);
+            value >= category_map.source_start
+                && offset < category_map.range_length
+                && value < u64::MAX
+                && category_map.destination_start.checked_add(offset).is_some_and(|destination| destination < u64::MAX)
+        })
+        .map_or(value, |category_map| value - category_map.source_start + category_map.destination_start)
+}
+
+fn apply_transform_linear(value: u64, transform: &Transform) -> u64 {
+    transform.maps.iter()
+        .find(|category_map| within_range(value, category_map))
+        .map_or(value, |category_map| apply_map(value, category_map))
+}
+
+// image of the intervals, through the maps they overlap
+fn transpose(intervals: IntervalSet, transform: &Transform) -> IntervalSet {
+    intervals.iter()
+        .flat_map(|interval| {
+            let interval_end = interval.end();
+            transform.maps[transform.position(interval.start)..].iter()
+                .take_while(move |category_map| category_map.source_start < interval_end)
+                .filter_map(move |category_map| overlap(interval, category_map))
+        })
+        .collect()
+}
+
+// every part of the intervals going through a map, the maps may overlap and leave gaps
+fn image(intervals: &IntervalSet, transform: &Transform) -> IntervalSet {
+    intervals.iter()
+        .flat_map(|interval| transform.maps.iter()
+            .filter_map(move |category_map| overlap(interval, category_map)))
+        .collect()
+}
+
+// image of the part of the interval within the map
+fn overlap(interval: &Interval, category_map: &CategoryMap) -> Option<Interval> {
+    let start = interval.start.max(category_map.source_start);
+    let end = interval.end().min(category_map.source_start + category_map.range_length);
+
+    if start < end {
+        Some(Interval { start: apply_map(start, category_map), length: end - start })
+    } else {
+        None
+    }
+}
+
+// values of the source category mapped into the intervals
+fn preimage(intervals: &IntervalSet, transform: &Transform) -> IntervalSet {
+    image(intervals, &transform.inverse())
+}
 
-    transposed
+// parse_text makes sure there are seeds, this is only a fallback
+fn no_seeds() -> Error {
+    Error::new(ErrorKind::InvalidData, "List of seeds is empty")
 }
 
-fn sort_intervals(intervals: &mut Vec<Interval>) -> () {
-    intervals.sort_by_key(|interval: &Interval| interval.start);    
+// part 2 reads the seeds as pairs of start and length
+fn seed_intervals(seeds: &[u64]) -> Vec<Interval> {
+    (0..seeds.len()/2).map(|i| Interval {
+            start: seeds[2*i],
+            length: seeds[2*i+1],
+        }).collect()
 }
 
 fn within_range(value: u64, category_map: &CategoryMap) -> bool {
@@ -269,5 +1080,5 @@ fn within_range(value: u64, category_map: &CategoryMap) -> bool {
 }
 
 fn apply_map(value: u64, category_map: &CategoryMap) -> u64 {
-    category_map.destination_start + value - category_map.source_start
+    value - category_map.source_start + category_map.destination_start
 }
\ No newline at end of file
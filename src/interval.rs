@@ -0,0 +1,156 @@
+use std::slice;
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct Interval {
+    pub start: u64,
+    pub length: u64,
+}
+
+impl Interval {
+    pub fn end(&self) -> u64 {
+        self.start + self.length
+    }
+}
+
+// sorted intervals, none of them empty, overlapping or touching another
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[derive(Default)]
+pub struct IntervalSet {
+    intervals: Vec<Interval>,
+}
+
+impl IntervalSet {
+    pub fn new() -> IntervalSet {
+        IntervalSet { intervals: Vec::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    pub fn total_length(&self) -> u64 {
+        self.intervals.iter()
+            .map(|interval| interval.length)
+            .sum()
+    }
+
+    pub fn min(&self) -> Option<u64> {
+        self.intervals.first()
+            .map(|interval| interval.start)
+    }
+
+    pub fn contains(&self, value: u64) -> bool {
+        let index = self.intervals.partition_point(|interval| interval.end() <= value);
+        index < self.intervals.len() && self.intervals[index].start <= value
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Interval> {
+        self.intervals.iter()
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        self.iter()
+            .chain(other.iter())
+            .cloned()
+            .collect()
+    }
+
+    pub fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        let mut intervals = Vec::new();
+        let (mut i, mut j) = (0, 0);
+
+        while i < self.intervals.len() && j < other.intervals.len() {
+            let a = &self.intervals[i];
+            let b = &other.intervals[j];
+
+            let start = a.start.max(b.start);
+            let end = a.end().min(b.end());
+            if start < end {
+                intervals.push(Interval { start, length: end - start });
+            }
+
+            // move past the interval ending first
+            if a.end() <= b.end() {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        IntervalSet { intervals }
+    }
+
+    pub fn difference(&self, other: &IntervalSet) -> IntervalSet {
+        let mut intervals = Vec::new();
+        let mut j = 0;
+
+        for interval in &self.intervals {
+            let mut start = interval.start;
+
+            // skip the intervals of other ending before this one
+            while j < other.intervals.len() && other.intervals[j].end() <= start {
+                j += 1;
+            }
+
+            let mut k = j;
+            while k < other.intervals.len() && other.intervals[k].start < interval.end() {
+                let removed = &other.intervals[k];
+                if start < removed.start {
+                    intervals.push(Interval { start, length: removed.start - start });
+                }
+                start = start.max(removed.end());
+                k += 1;
+            }
+
+            if start < interval.end() {
+                intervals.push(Interval { start, length: interval.end() - start });
+            }
+        }
+
+        IntervalSet { intervals }
+    }
+
+    // sort, then merge the overlapping and touching intervals
+    fn normalize(mut intervals: Vec<Interval>) -> IntervalSet {
+        intervals.retain(|interval| interval.length > 0);
+        intervals.sort_by_key(|interval| interval.start);
+
+        let mut merged: Vec<Interval> = Vec::with_capacity(intervals.len());
+        for interval in intervals {
+            if let Some(last) = merged.last_mut() {
+                if interval.start <= last.end() {
+                    last.length = last.end().max(interval.end()) - last.start;
+                    continue;
+                }
+            }
+            merged.push(interval);
+        }
+
+        IntervalSet { intervals: merged }
+    }
+}
+
+impl From<Vec<Interval>> for IntervalSet {
+    fn from(intervals: Vec<Interval>) -> IntervalSet {
+        IntervalSet::normalize(intervals)
+    }
+}
+
+impl FromIterator<Interval> for IntervalSet {
+    fn from_iter<I: IntoIterator<Item=Interval>>(iter: I) -> IntervalSet {
+        IntervalSet::normalize(iter.into_iter().collect())
+    }
+}
+
+impl<'a> IntoIterator for &'a IntervalSet {
+    type Item = &'a Interval;
+    type IntoIter = slice::Iter<'a, Interval>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
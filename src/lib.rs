@@ -1,12 +1,17 @@
 mod day1;
 mod day2;
 mod day3;
-mod day4;
+pub mod day4;
 mod day5;
 mod day6;
+pub mod interval;
+mod random;
 
 #[cfg(test)]
 mod tests {
+    use std::io::{Error, ErrorKind};
+    use std::time::Instant;
+
     const INPUT_FOLDER: &str = "inputs/";
     const FILE_ERROR_MESSAGE: &str = "Invalid input file";
 
@@ -16,6 +21,8 @@ mod tests {
     use super::day4;
     use super::day5;
     use super::day6;
+    use super::interval::{Interval, IntervalSet};
+    use super::random::Random;
 
     #[test]
     fn test_day1() {
@@ -52,19 +59,400 @@ mod tests {
     #[test]
     fn test_day3() {
         let filename: String = string_path("day3.txt");
-        let sum = day3::number_sum(filename)
+        let sum = day3::number_sum(filename, &day3::Neighborhood::Moore)
             .expect(FILE_ERROR_MESSAGE);
         assert_eq!(sum, 4361);
     }
 
+    #[test]
+    fn test_day3_neighborhood() {
+        let filename: String = string_path("day3.txt");
+        let sum = day3::number_sum(filename.clone(), &day3::Neighborhood::VonNeumann)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(sum, 2547);
+
+        let sum = day3::number_sum(filename.clone(), &day3::Neighborhood::MooreRadius(2))
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(sum, 4533);
+
+        let right = day3::Neighborhood::Custom(vec![(0, 1)]);
+        let sum = day3::number_sum(filename, &right)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(sum, 617);
+    }
+
     #[test]
     fn test_day3_2() {
         let filename: String = string_path("day3.txt");
-        let sum = day3::gear_ratio_sum(filename)
+        let sum = day3::gear_ratio_sum(filename, &day3::Neighborhood::Moore)
             .expect(FILE_ERROR_MESSAGE);
         assert_eq!(sum, 467835);
     }
 
+    #[test]
+    fn test_day3_gear_rule() {
+        let filename: String = string_path("day3.txt");
+        let moore = day3::Neighborhood::Moore;
+
+        let rule = day3::GearRule::new("*#", day3::Count::AtLeast(1), day3::Combine::Sum);
+        let sums = day3::gear_sums(filename.clone(), &moore, &rule)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(sums.get(&'*'), Some(&2472));
+        assert_eq!(sums.get(&'#'), Some(&633));
+
+        let rule = day3::GearRule::new("*", day3::Count::AtMost(1), day3::Combine::Max);
+        let sums = day3::gear_sums(filename.clone(), &moore, &rule)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(sums.get(&'*'), Some(&617));
+
+        let rule = day3::GearRule::new("*", day3::Count::Exact(2), day3::Combine::Max);
+        let sums = day3::gear_sums(filename, &moore, &rule)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(sums.get(&'*'), Some(&1222));
+    }
+
+    #[test]
+    fn test_day3_overflow() {
+        // three 8-digit numbers around each gear, their product is above u64::MAX
+        let engine = day3::parse_engine("99999999.99999999.\n........*...*.....\n99999999..........\n", day3::RaggedRows::Reject)
+            .expect(FILE_ERROR_MESSAGE);
+        let wide = day3::Neighborhood::MooreRadius(9);
+        let rule = day3::GearRule::new("*", day3::Count::AtLeast(1), day3::Combine::Product);
+        let sums = day3::gear_rule_sums(&engine, &wide, &rule);
+        assert_eq!(sums.get(&'*'), Some(&u64::MAX));
+        assert_eq!(day3::gear_rule_sums_labeled(&engine, &wide, &rule), sums);
+        assert_eq!(day3::tiled_gear_rule_sums(&engine, &wide, &rule, 2, 2).get(&'*'), Some(&u64::MAX));
+
+        let mut schematic = day3::schematic::Schematic::new(engine, &wide, &rule);
+        assert_eq!(schematic.gear_sum(), u64::MAX);
+        schematic.set_cell(1, 8, '.')
+            .expect("Cell is in the grid");
+        schematic.set_cell(1, 12, '.')
+            .expect("Cell is in the grid");
+        assert_eq!(schematic.gear_sum(), 0);
+        schematic.set_cell(1, 12, '*')
+            .expect("Cell is in the grid");
+        assert_eq!(schematic.gear_sum(), u64::MAX);
+
+        // a run too long for a u32 is never a part, on every path
+        let engine = day3::parse_engine("4294967296*7.\n", day3::RaggedRows::Reject)
+            .expect(FILE_ERROR_MESSAGE);
+        let moore = day3::Neighborhood::Moore;
+        let rule = day3::GearRule::new("*", day3::Count::AtLeast(1), day3::Combine::Sum);
+        assert_eq!(day3::part_number_sum(&engine, &moore), 7);
+        assert_eq!(day3::part_number_sum_with(&engine, &moore, day3::Edges::Wrap), 7);
+        assert_eq!(day3::part_number_sum_labeled(&engine, &moore), 7);
+        assert_eq!(day3::tiled_part_number_sum(&engine, &moore, 2, 2), 28);
+        assert_eq!(day3::gear_rule_sums(&engine, &moore, &rule).get(&'*'), Some(&7));
+        assert_eq!(day3::gear_rule_sums_with(&engine, &moore, &rule, day3::Edges::Wrap).get(&'*'), Some(&7));
+        assert_eq!(day3::gear_rule_sums_labeled(&engine, &moore, &rule).get(&'*'), Some(&7));
+
+        let mut schematic = day3::schematic::Schematic::new(engine, &moore, &rule);
+        assert_eq!(schematic.part_number_sum(), 7);
+        assert_eq!(schematic.gear_sum(), 7);
+        // shortening the run brings it back below u32::MAX
+        schematic.set_cell(0, 0, '.')
+            .expect("Cell is in the grid");
+        assert_eq!(schematic.part_number_sum(), 294967296 + 7);
+        assert_eq!(schematic.gear_sum(), 294967296 + 7);
+    }
+
+    #[test]
+    fn test_day3_labeled() {
+        let filename: String = string_path("day3.txt");
+        let engine = day3::engine(filename)
+            .expect(FILE_ERROR_MESSAGE);
+        let moore = day3::Neighborhood::Moore;
+        assert_eq!(day3::part_number_sum_labeled(&engine, &moore), 4361);
+        assert_eq!(day3::gear_rule_sums_labeled(&engine, &moore, &day3::GearRule::ratio()).get(&'*'), Some(&467835));
+
+        let neighborhoods = [
+            day3::Neighborhood::VonNeumann,
+            day3::Neighborhood::Moore,
+            day3::Neighborhood::MooreRadius(2),
+            day3::Neighborhood::Custom(vec![(0, 1), (1, 2), (-2, 0)]),
+        ];
+        let rule = day3::GearRule::new("*#+", day3::Count::AtLeast(1), day3::Combine::Sum);
+
+        for seed in 0..20 {
+            let engine = day3::random_engine(30, 40, seed);
+            for neighborhood in &neighborhoods {
+                assert_eq!(day3::part_number_sum_labeled(&engine, neighborhood), day3::part_number_sum(&engine, neighborhood));
+                assert_eq!(day3::gear_rule_sums_labeled(&engine, neighborhood, &rule), day3::gear_rule_sums(&engine, neighborhood, &rule));
+            }
+        }
+
+        // dots and digits in a rule never act as gears
+        let rule = day3::GearRule::new(".1*", day3::Count::AtLeast(1), day3::Combine::Sum);
+        let engine = day3::parse_engine("1.2\n...\n", day3::RaggedRows::Reject)
+            .expect(FILE_ERROR_MESSAGE);
+        let sums = day3::gear_rule_sums(&engine, &moore, &rule);
+        assert_eq!(sums, std::collections::BTreeMap::from([('.', 0), ('1', 0), ('*', 0)]));
+        assert_eq!(day3::gear_rule_sums_labeled(&engine, &moore, &rule), sums);
+        for seed in 0..20 {
+            let engine = day3::random_engine(30, 40, seed);
+            assert_eq!(day3::gear_rule_sums_labeled(&engine, &moore, &rule), day3::gear_rule_sums(&engine, &moore, &rule));
+        }
+    }
+
+    #[test]
+    fn test_day3_parsing() {
+        let filename: String = string_path("day3_crlf.txt");
+        let sum = day3::number_sum(filename, &day3::Neighborhood::Moore)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(sum, 4361);
+
+        let filename: String = string_path("day3_ragged.txt");
+        let error = day3::engine(filename.clone())
+            .err()
+            .expect("Ragged rows should be rejected");
+        assert_eq!(downcast::<day3::GridError>(&error), Some(&day3::GridError::RaggedRow { row: 5, expected: 10, found: 4 }));
+        assert_eq!(error.to_string(), "row 5 has 4 columns, expected 10");
+
+        let engine = day3::engine_with(filename, day3::RaggedRows::Pad)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(day3::part_number_sum(&engine, &day3::Neighborhood::Moore), 4361);
+
+        let filename: String = string_path("day3_empty.txt");
+        let error = day3::engine(filename)
+            .err()
+            .expect("Empty file should be rejected");
+        assert_eq!(downcast::<day3::GridError>(&error), Some(&day3::GridError::Empty));
+
+        let error = day3::parse_engine("..\n.é\n", day3::RaggedRows::Pad)
+            .err();
+        assert_eq!(error, Some(day3::GridError::NonAscii { row: 2, col: 2 }));
+    }
+
+    #[test]
+    fn test_day3_render() {
+        let filename: String = string_path("day3.txt");
+        let engine = day3::engine(filename)
+            .expect(FILE_ERROR_MESSAGE);
+        let moore = day3::Neighborhood::Moore;
+        let rule = day3::GearRule::ratio();
+
+        let ansi = day3::render::ansi(&engine, &moore, &rule);
+        assert!(ansi.starts_with("\x1b[32m467\x1b[90m..\x1b[31m114"));
+        assert!(ansi.contains("* col 4 = 16345"));
+        assert!(ansi.contains("* col 6 = 451490"));
+        assert_eq!(ansi.lines().count(), 10);
+
+        let svg = day3::render::svg(&engine, &moore, &rule);
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<title>gear ratio").count(), 2);
+        assert!(svg.contains("<title>gear ratio 16345</title>"));
+
+        let path = std::env::temp_dir().join("day3.svg");
+        day3::render::write_svg(&engine, &moore, &rule, path.to_string_lossy().to_string())
+            .expect("Cannot write SVG file");
+        assert_eq!(std::fs::read_to_string(path).ok(), Some(svg));
+
+        // control characters are replaced in both renderers
+        let engine = day3::parse_engine("1\x1b[2J\x07*\n", day3::RaggedRows::Reject)
+            .expect(FILE_ERROR_MESSAGE);
+        let ansi = day3::render::ansi(&engine, &moore, &rule);
+        assert!(!ansi.contains("\x1b[2J") && !ansi.contains('\x07'));
+        assert_eq!(ansi.matches('\u{fffd}').count(), 2);
+        let svg = day3::render::svg(&engine, &moore, &rule);
+        assert!(!svg.chars().any(|c| c.is_control() && c != '\n'));
+    }
+
+    #[test]
+    fn test_day3_schematic() {
+        let filename: String = string_path("day3.txt");
+        let engine = day3::engine(filename)
+            .expect(FILE_ERROR_MESSAGE);
+        let moore = day3::Neighborhood::Moore;
+        let rule = day3::GearRule::ratio();
+
+        let mut schematic = day3::schematic::Schematic::new(engine, &moore, &rule);
+        assert_eq!(schematic.part_number_sum(), 4361);
+        assert_eq!(schematic.gear_sum(), 467835);
+
+        // 114 becomes a part number, 467 is no longer one and 467 * 35 is no longer a gear
+        schematic.set_cell(1, 4, '#')
+            .expect("Cell is in the grid");
+        schematic.set_cell(1, 3, '.')
+            .expect("Cell is in the grid");
+        assert_eq!(schematic.part_number_sum(), 4361 + 114 - 467);
+        assert_eq!(schematic.gear_sum(), 467835 - 467 * 35);
+
+        // 467 and the dot on its right merge into 4675, which touches the '#'
+        schematic.set_cell(0, 3, '5')
+            .expect("Cell is in the grid");
+        assert_eq!(schematic.part_number_sum(), 4361 + 114 - 467 + 4675);
+
+        let error = schematic.set_cell(10, 0, '.')
+            .err();
+        assert_eq!(error, Some(day3::GridError::OutOfGrid { row: 11, col: 1 }));
+        let error = schematic.set_cell(0, 0, 'é')
+            .err();
+        assert_eq!(error, Some(day3::GridError::NonAscii { row: 1, col: 1 }));
+
+        // joining two runs goes past u32::MAX: the run is kept, but is never a part nor a gear number
+        let engine = day3::parse_engine("99999.99999*\n", day3::RaggedRows::Reject)
+            .expect(FILE_ERROR_MESSAGE);
+        let rule = day3::GearRule::new("*", day3::Count::AtLeast(1), day3::Combine::Sum);
+        let mut schematic = day3::schematic::Schematic::new(engine, &moore, &rule);
+        assert_eq!((schematic.part_number_sum(), schematic.gear_sum()), (99999, 99999));
+        schematic.set_cell(0, 5, '9')
+            .expect("Cell is in the grid");
+        assert_eq!((schematic.part_number_sum(), schematic.gear_sum()), (0, 0));
+        schematic.set_cell(0, 5, '.')
+            .expect("Cell is in the grid");
+        assert_eq!((schematic.part_number_sum(), schematic.gear_sum()), (99999, 99999));
+    }
+
+    #[test]
+    fn test_day3_schematic_random_edits() {
+        let neighborhood = day3::Neighborhood::Custom(vec![(0, 1), (1, 1), (-1, -2), (0, -1)]);
+        let rule = day3::GearRule::new("*#", day3::Count::AtLeast(1), day3::Combine::Sum);
+        let chars = ['1', '2', '7', '0', '.', '.', '*', '#', '+'];
+        let mut random = Random::new(5);
+
+        let mut schematic = day3::schematic::Schematic::new(day3::random_engine(20, 30, 5), &neighborhood, &rule);
+
+        for _ in 0..500 {
+            let row = random.below(20) as usize;
+            let col = random.below(30) as usize;
+            let c = chars[random.below(chars.len() as u64) as usize];
+            schematic.set_cell(row, col, c)
+                .expect("Cell is in the grid");
+
+            let engine = schematic.engine();
+            assert_eq!(schematic.part_number_sum(), day3::part_number_sum(engine, &neighborhood));
+            assert_eq!(schematic.gear_sum(), day3::gear_rule_sums(engine, &neighborhood, &rule).values().sum());
+        }
+    }
+
+    #[test]
+    fn test_day3_wrap() {
+        let moore = day3::Neighborhood::Moore;
+        let rule = day3::GearRule::new("#", day3::Count::AtLeast(1), day3::Combine::Sum);
+
+        let filename: String = string_path("day3.txt");
+        let engine = day3::engine(filename)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(day3::part_number_sum_with(&engine, &moore, day3::Edges::Wrap), 4361);
+
+        // 1 and 3 form 13 across the right edge, which touches the '#' across the bottom edge
+        let filename: String = string_path("day3_wrap.txt");
+        let engine = day3::engine(filename)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(day3::part_number_sum_with(&engine, &moore, day3::Edges::Clip), 5);
+        assert_eq!(day3::part_number_sum_with(&engine, &moore, day3::Edges::Wrap), 18);
+        assert_eq!(day3::gear_rule_sums_with(&engine, &moore, &rule, day3::Edges::Clip).get(&'#'), Some(&0));
+        assert_eq!(day3::gear_rule_sums_with(&engine, &moore, &rule, day3::Edges::Wrap).get(&'#'), Some(&13));
+    }
+
+    #[test]
+    fn test_day3_tiled() {
+        let filename: String = string_path("day3.txt");
+        let engine = day3::engine(filename)
+            .expect(FILE_ERROR_MESSAGE);
+        let moore = day3::Neighborhood::Moore;
+        let ratio = day3::GearRule::ratio();
+        assert_eq!(day3::tiled_part_number_sum(&engine, &moore, 1, 1), 4361);
+        assert_eq!(day3::tiled_part_number_sum(&engine, &moore, 1000, 1000), 4361 * 1000 * 1000);
+        assert_eq!(day3::tiled_gear_rule_sums(&engine, &moore, &ratio, 1000, 1000).get(&'*'), Some(&(467835 * 1000 * 1000)));
+
+        let neighborhoods = [
+            day3::Neighborhood::Moore,
+            day3::Neighborhood::MooreRadius(3),
+            day3::Neighborhood::Custom(vec![(0, 7), (-5, 1)]),
+        ];
+        let rule = day3::GearRule::new("*#+", day3::Count::AtLeast(1), day3::Combine::Sum);
+
+        for seed in 0..10 {
+            let engine = day3::random_engine(4, 5, seed);
+            for neighborhood in &neighborhoods {
+                for (tiles_down, tiles_across) in [(1, 1), (2, 3), (7, 9), (12, 4)] {
+                    let tiled = day3::tile(&engine, tiles_down, tiles_across);
+                    assert_eq!(day3::tiled_part_number_sum(&engine, neighborhood, tiles_down, tiles_across), day3::part_number_sum(&tiled, neighborhood));
+                    assert_eq!(day3::tiled_gear_rule_sums(&engine, neighborhood, &rule, tiles_down, tiles_across), day3::gear_rule_sums(&tiled, neighborhood, &rule));
+                }
+            }
+        }
+
+        // a row of digits only joins across every tile
+        let engine = day3::parse_engine("1\n*\n", day3::RaggedRows::Reject)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(day3::tiled_part_number_sum(&engine, &moore, 1, 6), 111111);
+        for text in ["1\n*\n", "12\n.*\n", "123\n*.#\n"] {
+            let engine = day3::parse_engine(text, day3::RaggedRows::Reject)
+                .expect(FILE_ERROR_MESSAGE);
+            for tiles_across in 1..=25 {
+                let tiled = day3::tile(&engine, 2, tiles_across);
+                assert_eq!(day3::tiled_part_number_sum(&engine, &moore, 2, tiles_across), day3::part_number_sum(&tiled, &moore));
+                assert_eq!(day3::tiled_gear_rule_sums(&engine, &moore, &rule, 2, tiles_across), day3::gear_rule_sums(&tiled, &moore, &rule));
+            }
+        }
+
+        // the weighted sum saturates
+        let engine = day3::parse_engine("4000000000*\n", day3::RaggedRows::Reject)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(day3::tiled_part_number_sum(&engine, &moore, 1 << 20, 1 << 20), u64::MAX);
+    }
+
+    #[test]
+    fn test_day3_stats() {
+        let filename: String = string_path("day3.txt");
+        let engine = day3::engine(filename)
+            .expect(FILE_ERROR_MESSAGE);
+
+        let statistics = day3::stats::statistics(&engine, &day3::Neighborhood::Moore);
+        assert_eq!(statistics.symbols().get(&'*'), Some(&3));
+        assert_eq!(statistics.symbols().values().sum::<usize>(), 6);
+        assert_eq!(statistics.touches().get(&'*').unwrap().get(&2), Some(&2));
+        assert!(statistics.shared_numbers().is_empty());
+        assert_eq!(statistics.components().len(), 6);
+        assert_eq!(statistics.components()[0].len(), 2);
+
+        let report = statistics.report();
+        assert!(report.contains("  * 1 x 1, 2 x 2\n"));
+        assert!(report.contains("6 components of 8 part numbers\n"));
+
+        let json = statistics.json();
+        assert!(json.starts_with("{\"symbols\":{\"#\":1,\"$\":1,\"*\":3,\"+\":1},"));
+        assert!(json.contains("\"components\":[[{\"value\":467,\"row\":1,\"col\":1},{\"value\":35,\"row\":3,\"col\":3}],"));
+
+        // with a radius of 2, 592 reaches 2 '*', the '+' and the '$'
+        let statistics = day3::stats::statistics(&engine, &day3::Neighborhood::MooreRadius(2));
+        assert!(statistics.shared_numbers().iter().any(|(location, symbols)| location.value == 592 && *symbols == 4));
+        assert!(statistics.report().contains("  592 at (7, 3) touches 4\n"));
+
+        // control characters are symbols too, escaped in JSON
+        let engine = day3::parse_engine("12\t\"\n", day3::RaggedRows::Reject)
+            .expect(FILE_ERROR_MESSAGE);
+        let json = day3::stats::statistics(&engine, &day3::Neighborhood::Moore).json();
+        assert!(json.starts_with("{\"symbols\":{\"\\u0009\":1,\"\\\"\":1},"));
+        assert!(!json.chars().any(|c| c.is_control()));
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_day3() {
+        let engine = day3::random_engine(10_000, 10_000, 3);
+        let moore = day3::Neighborhood::Moore;
+
+        let start = Instant::now();
+        let sum = day3::part_number_sum(&engine, &moore);
+        println!("part number sum {} in {:?}", sum, start.elapsed());
+
+        let start = Instant::now();
+        let sums = day3::gear_rule_sums(&engine, &moore, &day3::GearRule::ratio());
+        println!("gear ratio sum {:?} in {:?}", sums, start.elapsed());
+
+        let start = Instant::now();
+        let sum = day3::part_number_sum_labeled(&engine, &moore);
+        println!("labeled part number sum {} in {:?}", sum, start.elapsed());
+
+        let start = Instant::now();
+        let sums = day3::gear_rule_sums_labeled(&engine, &moore, &day3::GearRule::ratio());
+        println!("labeled gear ratio sum {:?} in {:?}", sums, start.elapsed());
+    }
+
     #[test]
     fn test_day4() {
         let filename: String = string_path("day4.txt");
@@ -81,6 +469,216 @@ mod tests {
         assert_eq!(sum, 30);
     }
 
+    #[test]
+    fn test_day4_cascade() {
+        let filename: String = string_path("day4_past_end.txt");
+        let count = day4::card_count_with(filename.clone(), day4::Cascade::Clip)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(count, 6);
+
+        // 2 copies of the virtual cards 4 and 5
+        let count = day4::card_count_with(filename.clone(), day4::Cascade::Extend)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(count, 10);
+
+        let error = day4::card_count_with(filename, day4::Cascade::Error)
+            .err()
+            .expect("Copies past the last card should be rejected");
+        assert_eq!(downcast::<day4::CascadeError>(&error), Some(&day4::CascadeError::PastLastCard { card: 2, last_copy: 5, last_card: 3 }));
+
+        // counts grow like Fibonacci numbers
+        let filename: String = string_path("day4_overflow.txt");
+        let error = day4::card_count(filename)
+            .err()
+            .expect("Card count should overflow");
+        assert!(matches!(downcast::<day4::CascadeError>(&error), Some(day4::CascadeError::Overflow { .. })));
+
+        // two cards with 70 matches each, worth 2^69 points
+        let numbers: Vec<String> = (1..=70).map(|number| number.to_string()).collect();
+        let line = format!("{} | {}", numbers.join(" "), numbers.join(" "));
+        let path = std::env::temp_dir().join("day4_wide.txt");
+        std::fs::write(&path, format!("Card 1: {}\nCard 2: {}\n", line, line))
+            .expect("Cannot write cards");
+        let filename = path.to_string_lossy().to_string();
+        let points = day4::card_point_sum(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(points, u64::MAX);
+
+        // copies far past the last card only visit the existing ones, or stop at the virtual card limit
+        let window = day4::CopiesOfNext::new(1 << 31);
+        let count = day4::card_count_with_rule(filename.clone(), &window, day4::Cascade::Clip)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(count, 3);
+        let error = day4::card_count_with_rule(filename.clone(), &window, day4::Cascade::Error)
+            .err()
+            .expect("Copies past the last card should be rejected");
+        assert!(matches!(downcast::<day4::CascadeError>(&error), Some(day4::CascadeError::PastLastCard { card: 1, .. })));
+        let error = day4::card_count_with_rule(filename, &window, day4::Cascade::Extend)
+            .err()
+            .expect("Too many virtual cards should be rejected");
+        assert!(matches!(downcast::<day4::CascadeError>(&error), Some(day4::CascadeError::VirtualCardLimit { card: 1, .. })));
+    }
+
+    #[test]
+    fn test_day4_ids() {
+        let filename: String = string_path("day4_shuffled.txt");
+        let count = day4::card_count(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(count, 30);
+        let issues = day4::card_issues(filename)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(issues, vec![
+            day4::CardIssue::OutOfOrder { id: 1, previous: 4 },
+            day4::CardIssue::OutOfOrder { id: 3, previous: 6 },
+            day4::CardIssue::OutOfOrder { id: 2, previous: 5 },
+        ]);
+
+        // copies of card 3 are dropped, or kept as a virtual card
+        let filename: String = string_path("day4_filtered.txt");
+        let count = day4::card_count(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(count, 14);
+        let count = day4::card_count_with(filename.clone(), day4::Cascade::Extend)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(count, 17);
+        let error = day4::card_count_with(filename.clone(), day4::Cascade::Error)
+            .err()
+            .expect("Copies of missing cards should be rejected");
+        assert_eq!(downcast::<day4::CascadeError>(&error), Some(&day4::CascadeError::MissingCard { card: 1, copy: 3 }));
+        let issues = day4::card_issues(filename)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(issues, vec![day4::CardIssue::Gap { after: 2, next: 4 }]);
+
+        let filename: String = string_path("day4_duplicate.txt");
+        let error = day4::card_count(filename.clone())
+            .err()
+            .expect("Duplicate cards should be rejected");
+        assert_eq!(downcast::<day4::CascadeError>(&error), Some(&day4::CascadeError::DuplicateCard { card: 2 }));
+        let issues = day4::card_issues(filename)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(issues, vec![
+            day4::CardIssue::Duplicate { id: 2 },
+            day4::CardIssue::OutOfOrder { id: 2, previous: 3 },
+        ]);
+    }
+
+    #[test]
+    fn test_day4_matching() {
+        for matching in [day4::Matching::Bitset, day4::Matching::Hashed] {
+            let filename: String = string_path("day4.txt");
+            let cards = day4::parse_cards_with(filename, matching)
+                .expect(FILE_ERROR_MESSAGE);
+            assert_eq!(day4::total_matches(&cards), 9);
+
+            // 183 is too large for a bitset, 1 is repeated in hand
+            let filename: String = string_path("day4_large.txt");
+            let cards = day4::parse_cards_with(filename, matching)
+                .expect(FILE_ERROR_MESSAGE);
+            assert_eq!(day4::total_matches(&cards), 4 + 2 + 5);
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_day4() {
+        let filename = std::env::temp_dir()
+            .join("day4_random.txt")
+            .to_string_lossy()
+            .to_string();
+        day4::write_random_cards(filename.clone(), 1_000_000, 4)
+            .expect("Cannot write cards");
+
+        for matching in [day4::Matching::Bitset, day4::Matching::Hashed] {
+            let start = Instant::now();
+            let cards = day4::parse_cards_with(filename.clone(), matching)
+                .expect(FILE_ERROR_MESSAGE);
+            let parsed = start.elapsed();
+
+            let start = Instant::now();
+            let matches = day4::total_matches(&cards);
+            println!("{:?}: {} matches in {:?}, parsed in {:?}", matching, matches, start.elapsed(), parsed);
+        }
+    }
+
+    #[test]
+    fn test_day4_rules() {
+        // a rule written outside of the module
+        struct Squares;
+        impl day4::ScoringRule for Squares {
+            fn points(&self, matches: usize) -> u64 {
+                (matches * matches) as u64
+            }
+        }
+
+        let filename: String = string_path("day4.txt");
+        let points = |rule: &dyn day4::ScoringRule| day4::card_point_sum_with_rule(filename.clone(), rule)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(points(&day4::Doubling), 13);
+        assert_eq!(points(&day4::Linear), 9);
+        assert_eq!(points(&day4::Fibonacci), 10);
+        assert_eq!(points(&Squares), 25);
+
+        let count = |rule: &dyn day4::ScoringRule| day4::card_count_with_rule(filename.clone(), rule, day4::Cascade::Clip)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(count(&Squares), 30);
+        assert_eq!(count(&day4::CopiesOfNext::new(1)), 16);
+    }
+
+    #[test]
+    fn test_day4_trace() {
+        let filename: String = string_path("day4.txt");
+        let traces = day4::card_count_trace(filename, &day4::Doubling, day4::Cascade::Clip)
+            .expect(FILE_ERROR_MESSAGE);
+        let copies: Vec<u64> = traces.iter().map(|trace| trace.copies).collect();
+        assert_eq!(copies, vec![1, 2, 4, 8, 14, 1]);
+        assert_eq!(traces[4].from, vec![(1, 1), (3, 4), (4, 8)]);
+        assert_eq!(traces[5].total, 30);
+
+        let table = day4::trace_table(&traces);
+        assert_eq!(table.lines().nth(5), Some("    5       0      14      29  1 x1, 3 x4, 4 x8"));
+        assert_eq!(table.lines().nth(6), Some("    6       0       1      30"));
+
+        let json = day4::trace_json(&traces);
+        assert!(json.starts_with("[{\"card\":1,\"original\":true,\"matches\":4,\"copies\":1,\"from\":[],\"total\":1},"));
+        assert!(json.contains("{\"card\":3,\"original\":true,\"matches\":2,\"copies\":4,\"from\":[{\"card\":1,\"copies\":1},{\"card\":2,\"copies\":2}],\"total\":7}"));
+
+        let filename: String = string_path("day4_past_end.txt");
+        let traces = day4::card_count_trace(filename, &day4::Doubling, day4::Cascade::Extend)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(day4::trace_table(&traces).lines().last(), Some("  (5)       0       2      10  2 x2"));
+    }
+
+    #[test]
+    fn test_day4_simulation() {
+        // shape of the example cards
+        let shape = day4::CardShape::new(5, 8, 99);
+        let simulation = day4::simulate(&shape, 6, 5000, &day4::Doubling, 40)
+            .expect("Card count should not overflow");
+
+        assert!((simulation.expected_matches - 40.0 / 99.0).abs() < 1e-9);
+        assert!((simulation.mean_matches - simulation.expected_matches).abs() < 0.02);
+        assert!((simulation.match_distribution.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        for (frequency, probability) in simulation.match_frequencies.iter().zip(simulation.match_distribution.iter()) {
+            assert!((frequency - probability).abs() < 0.01);
+        }
+        assert!((simulation.points.mean - simulation.expected_points).abs() < 0.05 * simulation.expected_points);
+        assert!(simulation.card_counts.min >= 6.0);
+        assert!(simulation.card_counts.mean > 6.0);
+
+        // seeded runs are reproducible
+        let again = day4::simulate(&shape, 6, 5000, &day4::Doubling, 40)
+            .expect("Card count should not overflow");
+        assert_eq!(again.card_counts.mean, simulation.card_counts.mean);
+
+        // no card, no statistics
+        for (cards_per_game, games) in [(0, 10), (6, 0)] {
+            let error = day4::simulate(&shape, cards_per_game, games, &day4::Doubling, 40)
+                .err()
+                .expect("An empty simulation should be rejected");
+            assert_eq!(error, day4::CascadeError::NoCards);
+        }
+    }
+
     #[test]
     fn test_day5() {
         let filename: String = string_path("day5.txt");
@@ -97,6 +695,429 @@ mod tests {
         assert_eq!(min, 46);
     }
 
+    #[test]
+    fn test_day5_categories() {
+        let filename: String = string_path("day5.txt");
+        let humidity = day5::map_value(filename.clone(), "soil", "humidity", 81)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(humidity, 78);
+        let error = day5::map_value(filename, "location", "seed", 82)
+            .err()
+            .expect("Location does not map to seed");
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+        let filename: String = string_path("day5_shuffled.txt");
+        let min = day5::lowest_location(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(min, 35);
+        let min = day5::lowest_location2(filename)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(min, 46);
+
+        // the water-to-light map is missing
+        let filename: String = string_path("day5_broken.txt");
+        let error = day5::lowest_location(filename)
+            .err()
+            .expect("Seed does not map to location");
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_day5_inverse() {
+        let filename: String = string_path("day5.txt");
+
+        // seed 82 lands in location 46
+        let seeds = day5::seeds_for_locations(filename.clone(), day5::Interval { start: 46, length: 1 })
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(seeds.iter().collect::<Vec<_>>(), vec![&day5::Interval { start: 82, length: 1 }]);
+
+        for (seed, location) in [(79, 82), (14, 43), (55, 86), (13, 35)] {
+            let seeds = day5::seeds_for_locations(filename.clone(), day5::Interval { start: location, length: 1 })
+                .expect(FILE_ERROR_MESSAGE);
+            assert!(seeds.contains(seed));
+        }
+
+        let seeds = day5::seeds_for_locations(filename.clone(), day5::Interval { start: 0, length: 100 })
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(seeds.total_length(), 100);
+
+        let verified = day5::verify_lowest_location2(filename)
+            .expect(FILE_ERROR_MESSAGE);
+        assert!(verified);
+    }
+
+    #[test]
+    fn test_day5_composed() {
+        let filename: String = string_path("day5.txt");
+        let min = day5::lowest_location_composed(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(min, 35);
+        let min = day5::lowest_location2_composed(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(min, 46);
+
+        // the composed map read back as an almanac with a single map
+        let composed = day5::composed_map(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        assert!(composed.starts_with("seed-to-location map:\n"));
+        let seeds = std::fs::read_to_string(&filename)
+            .expect(FILE_ERROR_MESSAGE)
+            .lines()
+            .next()
+            .expect(FILE_ERROR_MESSAGE)
+            .to_string();
+        let path = std::env::temp_dir().join("day5_composed.txt");
+        std::fs::write(&path, format!("{}\n\n{}", seeds, composed))
+            .expect(FILE_ERROR_MESSAGE);
+        let composed_filename = path.to_string_lossy().to_string();
+        for seed in 0..100 {
+            let location = day5::map_value(filename.clone(), "seed", "location", seed)
+                .expect(FILE_ERROR_MESSAGE);
+            let composed_location = day5::map_value(composed_filename.clone(), "seed", "location", seed)
+                .expect(FILE_ERROR_MESSAGE);
+            assert_eq!(location, composed_location);
+        }
+        let min = day5::lowest_location2(composed_filename)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(min, 46);
+
+        // an empty seed range has no location, even through the composed map
+        let path = std::env::temp_dir().join("day5_empty_range.txt");
+        std::fs::write(&path, "seeds: 79 0 55 13\n\nseed-to-location map:\n0 70 20\n")
+            .expect(FILE_ERROR_MESSAGE);
+        let filename = path.to_string_lossy().to_string();
+        let min = day5::lowest_location2_composed(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(min, 55);
+        let min = day5::lowest_location2(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(min, 55);
+        let min = day5::lowest_location2_brute_force(filename, 2)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(min, 55);
+    }
+
+    #[test]
+    fn test_day5_parsing() {
+        // tabs, runs of spaces, CRLF, comments and blank lines, even inside a block
+        let filename: String = string_path("day5_messy.txt");
+        let min = day5::lowest_location(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(min, 35);
+        let min = day5::lowest_location2_with(filename, day5::Validation::Strict)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(min, 46);
+
+        let path = std::env::temp_dir().join("day5_invalid.txt");
+        let filename = path.to_string_lossy().to_string();
+        let cases = [
+            ("", day5::AlmanacError::Empty),
+            ("# only a comment\n\n", day5::AlmanacError::Empty),
+            ("seed-to-soil map:\n1 2 3\n", day5::AlmanacError::MissingSeeds { line: 1 }),
+            ("seeds: 1 2\n50 98 2\n", day5::AlmanacError::MapOutsideBlock { line: 2 }),
+            ("seeds: 1 2\n\nseed to soil map:\n", day5::AlmanacError::InvalidHeader { line: 3 }),
+            ("seeds: 1 2\n\nseed-to-soil map:\n50 98\n", day5::AlmanacError::MapLength { line: 4, found: 2 }),
+            ("seeds: 1 2\n\nseed-to-soil map:\n50 98 2 1\n", day5::AlmanacError::MapLength { line: 4, found: 4 }),
+            ("seeds: 1 x2\n", day5::AlmanacError::InvalidNumber { line: 1, token: "x2".to_string() }),
+            ("seeds: 1 2\nseed-to-soil map:\n50 -98 2\n", day5::AlmanacError::InvalidNumber { line: 3, token: "-98".to_string() }),
+            ("seeds:\n\nseed-to-soil map:\n50 98 2\n", day5::AlmanacError::NoSeeds { line: 1 }),
+            ("# seeds below\nseeds: 7\n", day5::AlmanacError::OddSeeds { line: 2, count: 1 }),
+            ("seeds: 1 2 18446744073709551610 100\n", day5::AlmanacError::SeedRangeOverflow { line: 1, start: 18446744073709551610, length: 100 }),
+        ];
+        for (text, expected) in cases {
+            std::fs::write(&path, text)
+                .expect(FILE_ERROR_MESSAGE);
+            let error = day5::lowest_location(filename.clone())
+                .err()
+                .expect("Invalid almanac is accepted");
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+            assert_eq!(downcast::<day5::AlmanacError>(&error), Some(&expected));
+            let error = day5::lowest_location2(filename.clone())
+                .err()
+                .expect("Invalid almanac is accepted");
+            assert_eq!(downcast::<day5::AlmanacError>(&error), Some(&expected));
+        }
+        let error = day5::AlmanacError::MapLength { line: 4, found: 2 };
+        assert_eq!(error.to_string(), "line 4: expected 3 numbers in a map, found 2");
+    }
+
+    #[test]
+    fn test_day5_lowest_seed() {
+        let filename: String = string_path("day5.txt");
+
+        let lowest = day5::lowest_seed(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(lowest.seed, 13);
+        assert_eq!(lowest.seed_interval, None);
+        assert_eq!(lowest.chain.last(), Some(&("location".to_string(), 35)));
+
+        let lowest = day5::lowest_seed2(filename)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(lowest.seed, 82);
+        assert_eq!(lowest.seed_interval, Some(day5::Interval { start: 79, length: 14 }));
+        assert_eq!(lowest.to_string(),
+            "seed 82 (79..93) soil 84 fertilizer 84 water 84 light 77 temperature 45 humidity 46 location 46");
+
+        // the empty range 79..79 is never reported
+        let path = std::env::temp_dir().join("day5_empty_lowest.txt");
+        std::fs::write(&path, "seeds: 79 0 55 13\n\nseed-to-location map:\n0 70 20\n")
+            .expect(FILE_ERROR_MESSAGE);
+        let lowest = day5::lowest_seed2(path.to_string_lossy().to_string())
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(lowest.seed, 55);
+        assert_eq!(lowest.seed_interval, Some(day5::Interval { start: 55, length: 13 }));
+        assert_eq!(lowest.to_string(), "seed 55 (55..68) location 55");
+    }
+
+    #[test]
+    fn test_interval_set() {
+        let set = |intervals: &[(u64, u64)]| intervals.iter()
+            .map(|(start, length)| Interval { start: *start, length: *length })
+            .collect::<IntervalSet>();
+
+        // overlapping, touching and empty intervals are merged
+        let a = set(&[(10, 5), (0, 3), (3, 2), (12, 10), (40, 0)]);
+        assert_eq!(a, set(&[(0, 5), (10, 12)]));
+        assert_eq!(a.total_length(), 17);
+        assert_eq!(a.min(), Some(0));
+        assert!(a.contains(4) && !a.contains(5) && a.contains(21) && !a.contains(22));
+
+        let b = set(&[(2, 10), (20, 5)]);
+        assert_eq!(a.union(&b), set(&[(0, 25)]));
+        assert_eq!(a.intersection(&b), set(&[(2, 3), (10, 2), (20, 2)]));
+        assert_eq!(a.difference(&b), set(&[(0, 2), (12, 8)]));
+        assert_eq!(b.difference(&a), set(&[(5, 5), (22, 3)]));
+
+        let mut random = Random::new(5);
+        for _ in 0..1000 {
+            let mut random_set = || (0..random.below(6))
+                .map(|_| Interval { start: random.below(100), length: random.below(20) })
+                .collect::<IntervalSet>();
+            let (a, b) = (random_set(), random_set());
+
+            let union = a.union(&b);
+            let intersection = a.intersection(&b);
+            assert_eq!(union.total_length() + intersection.total_length(), a.total_length() + b.total_length());
+            assert_eq!(a.difference(&b).total_length(), a.total_length() - intersection.total_length());
+            assert_eq!(a.difference(&b).union(&intersection), a);
+            for value in 0..130 {
+                assert_eq!(union.contains(value), a.contains(value) || b.contains(value));
+                assert_eq!(intersection.contains(value), a.contains(value) && b.contains(value));
+            }
+        }
+    }
+
+    #[test]
+    fn test_day5_stages() {
+        let filename: String = string_path("day5.txt");
+        let seeds: IntervalSet = vec![Interval { start: 79, length: 14 }, Interval { start: 55, length: 13 }].into();
+        let stages = day5::stages(filename, seeds)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(stages.len(), 8);
+        assert_eq!(stages[7].0, "location");
+        assert_eq!(stages[7].1.min(), Some(46));
+        assert!(stages.iter().all(|(_, intervals)| intervals.total_length() == 27));
+
+        // random almanacs whose transforms are bijections
+        let path = std::env::temp_dir().join("day5_bijective.txt");
+        let filename = path.to_string_lossy().to_string();
+        let mut random = Random::new(45);
+        for _ in 0..100 {
+            let breaks = random.below(8) as usize;
+            std::fs::write(&path, bijective_almanac(&mut random, &["seed", "soil", "water", "location"], breaks, 1000))
+                .expect(FILE_ERROR_MESSAGE);
+
+            let seeds: IntervalSet = (0..random.below(5))
+                .map(|_| Interval { start: random.below(1200), length: random.below(200) })
+                .collect();
+            let stages = day5::stages(filename.clone(), seeds.clone())
+                .expect(FILE_ERROR_MESSAGE);
+            for (_, intervals) in &stages {
+                assert_eq!(intervals.total_length(), seeds.total_length());
+            }
+        }
+    }
+
+    #[test]
+    fn test_day5_validation() {
+        let filename: String = string_path("day5.txt");
+        let issues = day5::almanac_issues(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        assert!(issues.is_empty());
+        let min = day5::lowest_location2_with(filename, day5::Validation::Strict)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(min, 46);
+
+        let filename: String = string_path("day5_malformed.txt");
+        let issues = day5::almanac_issues(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        let seed_to_soil = "seed-to-soil".to_string();
+        assert_eq!(issues, vec![
+            day5::AlmanacIssue::Overlap { map: seed_to_soil.clone(), line: 4, other: 6 },
+            day5::AlmanacIssue::Overlap { map: seed_to_soil.clone(), line: 6, other: 5 },
+            day5::AlmanacIssue::ZeroLength { map: seed_to_soil.clone(), line: 7 },
+            day5::AlmanacIssue::Overflow { map: "soil-to-location".to_string(), line: 10 },
+        ]);
+        assert_eq!(issues[0].to_string(), "line 4: seed-to-soil map overlaps the one on line 6");
+
+        // the map starting first wins: seed 98 goes through the map on line 6
+        let soil = day5::map_value(filename.clone(), "seed", "soil", 98)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(soil, 18);
+        let min = day5::lowest_location(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(min, 10);
+        let min = day5::lowest_location2(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(min, 10);
+
+        let error = day5::lowest_location_with(filename, day5::Validation::Strict)
+            .err()
+            .expect("Malformed almanac is accepted");
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(downcast::<day5::AlmanacIssue>(&error), Some(&issues[0]));
+    }
+
+    #[test]
+    fn test_day5_lookup() {
+        let filename: String = string_path("day5.txt");
+        let seeds = [79, 14, 55, 13, 82, 0, 99, 100, u64::MAX - 1];
+        for lookup in [day5::Lookup::Linear, day5::Lookup::BinarySearch, day5::Lookup::Merge] {
+            let locations = day5::locations(filename.clone(), &seeds, lookup)
+                .expect(FILE_ERROR_MESSAGE);
+            assert_eq!(locations[..5], [82, 43, 86, 35, 46]);
+            assert_eq!(locations[8], u64::MAX - 1);
+        }
+
+        let path = std::env::temp_dir().join("day5_lookup.txt");
+        let filename = path.to_string_lossy().to_string();
+        let mut random = Random::new(47);
+        std::fs::write(&path, bijective_almanac(&mut random, &["seed", "soil", "water", "location"], 50, 10_000))
+            .expect(FILE_ERROR_MESSAGE);
+        let seeds: Vec<u64> = (0..1000).map(|_| random.below(12_000)).collect();
+        let linear = day5::locations(filename.clone(), &seeds, day5::Lookup::Linear)
+            .expect(FILE_ERROR_MESSAGE);
+        for lookup in [day5::Lookup::BinarySearch, day5::Lookup::Merge] {
+            let locations = day5::locations(filename.clone(), &seeds, lookup)
+                .expect(FILE_ERROR_MESSAGE);
+            assert_eq!(locations, linear);
+        }
+    }
+
+    #[test]
+    fn test_day5_brute_force() {
+        let filename: String = string_path("day5.txt");
+        for threads in [0, 1, 3] {
+            let min = day5::lowest_location2_brute_force(filename.clone(), threads)
+                .expect(FILE_ERROR_MESSAGE);
+            assert_eq!(min, 46);
+        }
+
+        // overlapping and overflowing maps, read the same way by both sides
+        let filename: String = string_path("day5_malformed.txt");
+        let min = day5::lowest_location2_brute_force(filename.clone(), 2)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(min, day5::lowest_location2(filename)
+            .expect(FILE_ERROR_MESSAGE));
+    }
+
+    #[test]
+    fn test_day5_generator() {
+        let shape = day5::AlmanacShape::new(8, 50, u64::MAX - 1, 0.7, 20)
+            .expect("Shape is valid");
+        let almanac = day5::random_almanac(&shape, 49);
+        assert_eq!(almanac, day5::random_almanac(&shape, 49));
+        assert_ne!(almanac, day5::random_almanac(&shape, 50));
+        assert_eq!(almanac.lines().filter(|line| line.ends_with("map:")).count(), 7);
+
+        let path = std::env::temp_dir().join("day5_generated.txt");
+        let filename = path.to_string_lossy().to_string();
+        day5::write_random_almanac(filename.clone(), &shape, 49)
+            .expect(FILE_ERROR_MESSAGE);
+        let issues = day5::almanac_issues(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        assert!(issues.is_empty());
+
+        let min = day5::lowest_location2_with(filename.clone(), day5::Validation::Strict)
+            .expect(FILE_ERROR_MESSAGE);
+        let composed = day5::lowest_location2_composed(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(min, composed);
+        let lowest = day5::lowest_seed2(filename)
+            .expect(FILE_ERROR_MESSAGE);
+        assert_eq!(lowest.chain.last().map(|(_, location)| *location), Some(min));
+
+        // not an almanac without seeds or maps
+        assert_eq!(day5::AlmanacShape::new(3, 5, 100, 0.5, 0).err(), Some(day5::ShapeError::NoSeeds));
+        assert_eq!(day5::AlmanacShape::new(3, 0, 100, 0.5, 2).err(), Some(day5::ShapeError::NoMaps));
+        assert_eq!(day5::AlmanacShape::new(1, 5, 100, 0.5, 2).err(), Some(day5::ShapeError::TooFewCategories { categories: 1 }));
+        assert_eq!(day5::AlmanacShape::new(3, 5, 100, 1.5, 2).err(), Some(day5::ShapeError::Coverage { coverage: 1.5 }));
+    }
+
+    #[test]
+    fn test_day5_generator_differential() {
+        // generated almanacs of every shape, solved by every path
+        let path = std::env::temp_dir().join("day5_generated_differential.txt");
+        let filename = path.to_string_lossy().to_string();
+        let mut random = Random::new(49);
+        for seed in 0..2000 {
+            let shape = day5::AlmanacShape::new(2 + random.below(4) as usize, 1 + random.below(8) as usize, 100, random.below(11) as f64 / 10.0, 1 + random.below(3) as usize)
+                .expect("Shape is valid");
+            day5::write_random_almanac(filename.clone(), &shape, seed)
+                .expect(FILE_ERROR_MESSAGE);
+
+            let expected = day5::lowest_location2_brute_force(filename.clone(), 2)
+                .expect(FILE_ERROR_MESSAGE);
+            let min = day5::lowest_location2(filename.clone())
+                .expect(FILE_ERROR_MESSAGE);
+            assert_eq!(min, expected, "{}", std::fs::read_to_string(&path).expect(FILE_ERROR_MESSAGE));
+            let min = day5::lowest_location2_composed(filename.clone())
+                .expect(FILE_ERROR_MESSAGE);
+            assert_eq!(min, expected);
+            let lowest = day5::lowest_seed2(filename.clone())
+                .expect(FILE_ERROR_MESSAGE);
+            assert_eq!(lowest.chain.last().map(|(_, location)| *location), Some(expected));
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_day5() {
+        let path = std::env::temp_dir().join("day5_random.txt");
+        let filename = path.to_string_lossy().to_string();
+        let shape = day5::AlmanacShape::new(8, 5000, u64::MAX - 1, 0.8, 10)
+            .expect("Shape is valid");
+        day5::write_random_almanac(filename.clone(), &shape, 5)
+            .expect("Cannot write almanac");
+
+        let mut random = Random::new(5);
+        let seeds: Vec<u64> = (0..100_000).map(|_| random.next_u64() >> 1).collect();
+        let mut sorted = seeds.clone();
+        sorted.sort();
+
+        let start = Instant::now();
+        let locator = day5::locator(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        println!("parsed and composed in {:?}", start.elapsed());
+
+        for lookup in [day5::Lookup::Linear, day5::Lookup::BinarySearch, day5::Lookup::Merge] {
+            for (order, seeds) in [("random", &seeds), ("sorted", &sorted)] {
+                let start = Instant::now();
+                let locations = locator.locations(seeds, lookup);
+                println!("{:?}, {} seeds: lowest location {:?} in {:?}", lookup, order, locations.iter().min(), start.elapsed());
+            }
+        }
+
+        let start = Instant::now();
+        let min = day5::lowest_location2(filename.clone())
+            .expect(FILE_ERROR_MESSAGE);
+        println!("intervals: lowest location {} in {:?}", min, start.elapsed());
+        let start = Instant::now();
+        let min = day5::lowest_location2_composed(filename)
+            .expect(FILE_ERROR_MESSAGE);
+        println!("composed: lowest location {} in {:?}", min, start.elapsed());
+    }
+
     #[test]
     fn test_day6() {
         let filename: String = string_path("day6.txt");
@@ -113,6 +1134,37 @@ mod tests {
         assert_eq!(product, 71503);
     }
 
+    // almanac with transforms permuting blocks of 0..size, up to `breaks` + 1 blocks each
+    fn bijective_almanac(random: &mut Random, categories: &[&str], breaks: usize, size: u64) -> String {
+        let mut text = String::from("seeds: 0 1\n");
+        for pair in categories.windows(2) {
+            text += &format!("\n{}-to-{} map:\n", pair[0], pair[1]);
+            let mut cuts: Vec<u64> = (0..breaks).map(|_| random.below(size)).collect();
+            cuts.extend([0, size]);
+            cuts.sort();
+            cuts.dedup();
+            let mut blocks: Vec<(u64, u64)> = cuts.windows(2)
+                .map(|pair| (pair[0], pair[1] - pair[0]))
+                .collect();
+            for i in (1..blocks.len()).rev() {
+                blocks.swap(i, random.below(i as u64 + 1) as usize);
+            }
+            // the shuffled blocks are laid out one after the other
+            let mut destination = 0;
+            for (source, length) in blocks {
+                text += &format!("{} {} {}\n", destination, source, length);
+                destination += length;
+            }
+        }
+        text
+    }
+
+    // structured error wrapped in an io::Error
+    fn downcast<T: std::error::Error + 'static>(error: &Error) -> Option<&T> {
+        error.get_ref()
+            .and_then(|error| error.downcast_ref())
+    }
+
     fn string_path(filename: &str) -> String {
         return INPUT_FOLDER.to_owned() + filename;
     }
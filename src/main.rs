@@ -11,6 +11,8 @@ mod day5;
 use day5::lowest_location2;
 mod day6;
 use day6::ways_product2;
+mod interval;
+mod random;
 
 
 fn main() {